@@ -87,6 +87,8 @@ fn bench<B: Backend>(device: &B::Device) -> Vec<BenchmarkResult> {
     vec![run_benchmark(to_benchmark), run_benchmark(from_benchmark)]
 }
 
+burnbench::counting_allocator!();
+
 fn main() {
     burnbench::bench_on_backend!();
 }
@@ -108,6 +108,8 @@ fn bench<B: Backend>(device: &B::Device) -> Vec<BenchmarkResult> {
         .collect()
 }
 
+burnbench::counting_allocator!();
+
 fn main() {
     burnbench::bench_on_backend!();
 }
@@ -0,0 +1,14 @@
+use burnbench::BACKENDS;
+
+#[test]
+fn features_match_the_backend_registry() {
+    let content = include_str!("../Cargo.toml");
+
+    for backend in BACKENDS {
+        assert!(
+            content.contains(&backend.feature_definition(None)),
+            "backend-comparison should define the feature: {}",
+            backend.feature_definition(None)
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use std::env;
+use std::process::Command;
+
+/// Embed the toolchain used to compile the benchmarks in the system information.
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let target = env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+    // Flags are separated by the 0x1f character, see the cargo documentation.
+    let rustflags = env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+
+    println!("cargo:rustc-env=BURNBENCH_RUSTC_VERSION={rustc_version}");
+    println!("cargo:rustc-env=BURNBENCH_TARGET={target}");
+    println!("cargo:rustc-env=BURNBENCH_RUSTFLAGS={rustflags}");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=RUSTC");
+    println!("cargo:rerun-if-env-changed=CARGO_ENCODED_RUSTFLAGS");
+}
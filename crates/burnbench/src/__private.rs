@@ -21,6 +21,19 @@ pub fn get_argument<'a>(args: &'a [String], arg_name: &'a str) -> Option<&'a str
     None
 }
 
+/// Environment variable used by the runner to forward the commit of burn resolved by the build to
+/// the benchmark binaries.
+pub const BURN_COMMIT_ENV_VAR: &str = "BURN_BENCH_BURN_COMMIT";
+
+/// The commit of burn built for the bench, resolved by the runner.
+///
+/// Returns `None` when burn is not a git dependency or when the bench is not executed by the runner.
+pub fn resolved_burn_commit() -> Option<String> {
+    std::env::var(BURN_COMMIT_ENV_VAR)
+        .ok()
+        .filter(|commit| !commit.is_empty())
+}
+
 /// Specialized function to retrieve the sharing token
 pub fn get_sharing_token(args: &[String]) -> Option<&str> {
     get_argument(args, "--sharing-token")
@@ -62,6 +75,11 @@ fn update_panic_hook() {
     }));
 }
 
+/// Define the `__save_result` function used by [bench_on_backend](crate::bench_on_backend) to
+/// persist the results of a bench, and to upload them when a sharing token is passed.
+///
+/// It is expanded by `bench_on_backend!()` and should only be called directly by benches calling
+/// `bench_on_backend!(bench, <dtype>)` themselves.
 #[macro_export]
 macro_rules! define_types {
     () => {
@@ -69,26 +87,37 @@ macro_rules! define_types {
             benches: Vec<$crate::BenchmarkResult>,
             backend_name: String,
             device: String,
+            device_selector: String,
             url: Option<&str>,
             token: Option<&str>,
             feature: &str,
         ) {
             let burn_version =
                 std::env::var("BURN_BENCH_BURN_VERSION").unwrap_or_else(|_| "main".to_string());
+            let burn_commit = $crate::__private::resolved_burn_commit();
+            let environment = $crate::EnvironmentChecks::from_env();
+            let execution = $crate::ExecutionSettings::applied();
 
             let records: Vec<$crate::BenchmarkRecord> = benches
                 .into_iter()
+                // Benchmarks excluded by the filter have not been executed.
+                .filter(|bench| !bench.raw.durations.is_empty())
                 .map(|bench| $crate::BenchmarkRecord {
                     backend: backend_name.clone(),
                     device: device.clone(),
+                    device_selector: Some(device_selector.clone()),
                     feature: feature.to_string(),
                     burn_version: burn_version.clone(),
+                    burn_commit: burn_commit.clone(),
                     system_info: $crate::BenchmarkSystemInfo::new(),
+                    environment: environment.clone(),
+                    execution: execution.clone(),
+                    // Set when the record is saved in the current run
+                    run: Default::default(),
+                    schema_version: $crate::SCHEMA_VERSION,
+                    extra: Default::default(),
                     results: $crate::BenchmarkResult {
-                        raw: $crate::BenchmarkDurations {
-                            timing_method: Default::default(),
-                            durations: bench.raw.durations,
-                        },
+                        raw: bench.raw,
                         computed: $crate::BenchmarkComputations {
                             mean: bench.computed.mean,
                             median: bench.computed.median,
@@ -96,7 +125,10 @@ macro_rules! define_types {
                             min: bench.computed.min,
                             max: bench.computed.max,
                         },
+                        memory: bench.memory,
+                        metrics: bench.metrics,
                         git_hash: bench.git_hash,
+                        git_dirty: bench.git_dirty,
                         name: bench.name,
                         options: bench.options,
                         shapes: bench.shapes,
@@ -110,6 +142,35 @@ macro_rules! define_types {
     };
 }
 
+/// Create the wgpu device matching the given [device selector](crate::DeviceSelector).
+#[macro_export]
+macro_rules! wgpu_device {
+    ($selector:expr) => {{
+        use burn::backend::wgpu::WgpuDevice;
+
+        match $selector {
+            $crate::DeviceSelector::Default => WgpuDevice::DefaultDevice,
+            $crate::DeviceSelector::Cpu => WgpuDevice::Cpu,
+            $crate::DeviceSelector::Discrete(index) => WgpuDevice::DiscreteGpu(index),
+            $crate::DeviceSelector::Integrated(index) => WgpuDevice::IntegratedGpu(index),
+            $crate::DeviceSelector::Virtual(index) => WgpuDevice::VirtualGpu(index),
+        }
+    }};
+}
+
+/// Execute the `bench` function of a bench on the backend selected by the crate features and
+/// save its results.
+///
+/// The benchmarked crate must:
+/// - define a `bench<B: burn::tensor::backend::Backend>(device: &B::Device) -> Vec<BenchmarkResult>`
+///   function in each bench, and call `burnbench::bench_on_backend!()` from its `main` function;
+/// - depend on `burn` with `default-features = false` and on `burnbench`;
+/// - declare the features `f32`, `f16`, `bf16`, `flex32`, `legacy-v16` and `legacy-v17`, the
+///   backend features are generated by the runner from the registered
+///   [backends](crate::BACKENDS).
+///
+/// The other forms are `bench_on_backend!(bench, <dtype>)` which runs on the selected backend with
+/// the given float element type, and `bench_on_backend!(bench, <backend type>, <device>)`.
 #[macro_export]
 macro_rules! bench_on_backend {
     () => {{
@@ -140,104 +201,8 @@ macro_rules! bench_on_backend {
         $crate::__private::init_log().unwrap();
         use std::env;
 
-        #[cfg(feature = "cuda")]
-        {
-            #[cfg(not(feature = "legacy-v16"))]
-            use burn::backend::Cuda;
-            #[cfg(feature = "legacy-v16")]
-            use burn::backend::CudaJit as Cuda;
-
-            let device = Default::default();
-            $crate::bench_on_backend!($fn_name, Cuda<$dtype>, device);
-        }
-
-        #[cfg(feature = "metal")]
-        {
-            use burn::backend::Metal;
-
-            let device = Default::default();
-            $crate::bench_on_backend!($fn_name, Metal<$dtype>, device);
-        }
-
-        #[cfg(all(target_os = "macos", feature = "vulkan"))]
-        {
-            panic!("vulkan benchmarks are not supported on macOS, use the wgpu backend instead.");
-        }
-
-        #[cfg(any(feature = "wgpu", feature = "vulkan"))]
-        {
-            use burn::backend::Wgpu;
-
-            let device = Default::default();
-            $crate::bench_on_backend!($fn_name, Wgpu<$dtype>, device);
-        }
-
-        #[cfg(feature = "ndarray")]
-        {
-            use burn::backend::NdArray;
-
-            let device = Default::default();
-            $crate::bench_on_backend!($fn_name, NdArray<$dtype>, device);
-        }
-
-        #[cfg(feature = "tch-cpu")]
-        {
-            use burn::backend::{LibTorch, libtorch::LibTorchDevice};
-
-            let device = LibTorchDevice::Cpu;
-            $crate::bench_on_backend!($fn_name, LibTorch<$dtype>, device);
-        }
-
-        #[cfg(feature = "tch-cuda")]
-        {
-            use burn::backend::{LibTorch, libtorch::LibTorchDevice};
-
-            let device = LibTorchDevice::Cuda(0);
-            $crate::bench_on_backend!($fn_name, LibTorch<$dtype>, device);
-        }
-
-        #[cfg(feature = "tch-metal")]
-        {
-            use burn::backend::{LibTorch, libtorch::LibTorchDevice};
-
-            let device = LibTorchDevice::Mps;
-            $crate::bench_on_backend!($fn_name, LibTorch<$dtype>, device);
-        }
-
-        #[cfg(feature = "candle-cpu")]
-        {
-            use burn::backend::candle::{Candle, CandleDevice};
-
-            let device = CandleDevice::Cpu;
-            $crate::bench_on_backend!($fn_name, Candle<$dtype>, device);
-        }
-
-        #[cfg(feature = "candle-cuda")]
-        {
-            use burn::backend::candle::{Candle, CandleDevice};
-
-            let device = CandleDevice::cuda(0);
-            $crate::bench_on_backend!($fn_name, Candle<$dtype>, device);
-        }
-
-        #[cfg(feature = "candle-metal")]
-        {
-            use burn::backend::candle::{Candle, CandleDevice};
-
-            let device = CandleDevice::metal(0);
-            $crate::bench_on_backend!($fn_name, Candle<$dtype>, device);
-        }
-
-        #[cfg(feature = "rocm")]
-        {
-            #[cfg(feature = "legacy-v16")]
-            use burn::backend::HipJit as Hip;
-            #[cfg(not(feature = "legacy-v16"))]
-            use burn::backend::Rocm;
-
-            let device = Default::default();
-            $crate::bench_on_backend!($fn_name, Rocm<$dtype>, device);
-        }
+        let selector = $crate::DeviceSelector::from_env();
+        $crate::__bench_on_registered_backend!($fn_name, $dtype, selector);
     }};
 
     ($fn_name:ident, $backend:ty, $device:ident) => {
@@ -245,57 +210,27 @@ macro_rules! bench_on_backend {
         let url = $crate::__private::get_sharing_url(&args);
         let token = $crate::__private::get_sharing_token(&args);
 
-        #[cfg(feature = "candle-accelerate")]
-        let feature_name = "candle-accelerate";
-        #[cfg(feature = "candle-cpu")]
-        let feature_name = "candle-cpu";
-        #[cfg(feature = "candle-cuda")]
-        let feature_name = "candle-cuda";
-        #[cfg(feature = "candle-metal")]
-        let feature_name = "candle-metal";
-        #[cfg(feature = "ndarray")]
-        let feature_name = "ndarray";
-        #[cfg(feature = "ndarray-simd")]
-        let feature_name = "ndarray-simd";
-        #[cfg(feature = "ndarray-blas-accelerate")]
-        let feature_name = "ndarray-blas-accelerate";
-        #[cfg(feature = "ndarray-blas-netlib")]
-        let feature_name = "ndarray-blas-netlib";
-        #[cfg(feature = "ndarray-blas-openblas")]
-        let feature_name = "ndarray-blas-openblas";
-        #[cfg(feature = "tch-cpu")]
-        let feature_name = "tch-cpu";
-        #[cfg(feature = "tch-cuda")]
-        let feature_name = "tch-cuda";
-        #[cfg(feature = "tch-metal")]
-        let feature_name = "tch-metal";
-        #[cfg(feature = "wgpu")]
-        let feature_name = "wgpu";
-        #[cfg(feature = "wgpu-fusion")]
-        let feature_name = "wgpu-fusion";
-        #[cfg(feature = "vulkan")]
-        let feature_name = "vulkan";
-        #[cfg(feature = "vulkan-fusion")]
-        let feature_name = "vulkan-fusion";
-        #[cfg(feature = "metal")]
-        let feature_name = "metal";
-        #[cfg(feature = "metal-fusion")]
-        let feature_name = "metal-fusion";
-        #[cfg(feature = "cuda")]
-        let feature_name = "cuda";
-        #[cfg(feature = "cuda-fusion")]
-        let feature_name = "cuda-fusion";
-        #[cfg(feature = "rocm")]
-        let feature_name = "rocm";
-        #[cfg(feature = "rocm-fusion")]
-        let feature_name = "rocm-fusion";
+        let feature_name = $crate::__backend_feature_name!();
 
         let device_name = format!("{:?}", &$device);
+        let selector = $crate::DeviceSelector::from_env();
+        let device_selector = match $crate::get_backend(feature_name) {
+            Some(backend) => selector.normalized(backend),
+            None => selector,
+        };
         #[cfg(not(feature = "legacy-v16"))]
         let backend_name = <$backend as Backend>::name(&$device);
         #[cfg(feature = "legacy-v16")]
         let backend_name = <$backend as Backend>::name();
         let benches = $fn_name::<$backend>(&$device);
-        __save_result(benches, backend_name, device_name, url, token, feature_name);
+        __save_result(
+            benches,
+            backend_name,
+            device_name,
+            device_selector.to_string(),
+            url,
+            token,
+            feature_name,
+        );
     };
 }
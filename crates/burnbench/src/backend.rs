@@ -0,0 +1,449 @@
+use semver::Version;
+
+use crate::{DeviceSupport, GpuEnumeration};
+
+/// Description of a backend that can be benchmarked.
+///
+/// Every backend is registered once in [BACKENDS], the command line arguments, the feature flags
+/// of the benchmarked crate and the [bench_on_backend](crate::bench_on_backend) macro are all
+/// derived from it.
+#[derive(Debug)]
+pub struct BackendDescriptor {
+    /// Name of the backend, it is also the name of the crate feature enabling it.
+    pub name: &'static str,
+    /// Features enabled by the crate feature of the backend.
+    pub features: &'static [&'static str],
+    /// Kind of devices the backend can run on.
+    pub devices: DeviceSupport,
+    /// How the GPUs the backend runs on are enumerated.
+    pub gpus: GpuEnumeration,
+    /// Features to enable instead of `features` with older Burn versions.
+    pub legacy: &'static [LegacyFeatures],
+    /// Operating systems on which the backend is available, all of them when empty.
+    pub platforms: &'static [&'static str],
+}
+
+/// Features of a backend for the Burn versions prior to `before`.
+#[derive(Debug)]
+pub struct LegacyFeatures {
+    /// First Burn version which uses the current features.
+    pub before: &'static str,
+    /// Features enabled by the crate feature of the backend.
+    pub features: &'static [&'static str],
+}
+
+impl BackendDescriptor {
+    /// Returns true if the backend is available on the current operating system.
+    pub fn is_supported_platform(&self) -> bool {
+        self.platforms.is_empty() || self.platforms.contains(&std::env::consts::OS)
+    }
+
+    /// Features enabled by the crate feature of the backend for the given Burn version.
+    ///
+    /// Git branches and local checkouts have no version and use the current features.
+    pub fn features_for(&self, version: Option<&Version>) -> &'static [&'static str] {
+        let Some(version) = version else {
+            return self.features;
+        };
+
+        self.legacy
+            .iter()
+            .filter(|legacy| version < &Version::parse(legacy.before).unwrap())
+            .min_by_key(|legacy| Version::parse(legacy.before).unwrap())
+            .map(|legacy| legacy.features)
+            .unwrap_or(self.features)
+    }
+
+    /// Definition of the crate feature of the backend, as written in a Cargo.toml file.
+    pub fn feature_definition(&self, version: Option<&Version>) -> String {
+        let features = self
+            .features_for(version)
+            .iter()
+            .map(|feature| format!("\"{feature}\""))
+            .collect::<Vec<_>>();
+        format!("{} = [{}]", self.name, features.join(", "))
+    }
+}
+
+/// Return the descriptor of the backend with the given name.
+pub fn get_backend(name: &str) -> Option<&'static BackendDescriptor> {
+    BACKENDS.iter().find(|backend| backend.name == name)
+}
+
+/// All the registered backends available on the current operating system.
+pub fn available_backends() -> impl Iterator<Item = &'static BackendDescriptor> {
+    BACKENDS
+        .iter()
+        .filter(|backend| backend.is_supported_platform())
+}
+
+/// The leading `bench` arguments declare the metavariables of the generated
+/// `__bench_on_registered_backend` macro used by the `bench` blocks of the backends.
+macro_rules! register_backends {
+    (bench $bench_args:tt; $(
+        $name:literal => {
+            features: [$($feature:literal),* $(,)?],
+            devices: $devices:ident,
+            gpus: $gpus:ident,
+            legacy: [$(($before:literal, [$($legacy:literal),* $(,)?])),* $(,)?],
+            platforms: [$($platform:literal),* $(,)?]
+            $(, bench: { $($bench:tt)* })? $(,)?
+        }
+    ),* $(,)?) => {
+        /// All the registered backends.
+        ///
+        /// A backend whose crate feature enables the feature of another backend, like the fusion
+        /// variants, must be registered after it.
+        pub static BACKENDS: &[BackendDescriptor] = &[$(
+            BackendDescriptor {
+                name: $name,
+                features: &[$($feature),*],
+                devices: DeviceSupport::$devices,
+                gpus: GpuEnumeration::$gpus,
+                legacy: &[$(LegacyFeatures {
+                    before: $before,
+                    features: &[$($legacy),*],
+                }),*],
+                platforms: &[$($platform),*],
+            }
+        ),*];
+
+        /// Name of the backend enabled in the crate expanding the macro.
+        #[doc(hidden)]
+        #[macro_export]
+        macro_rules! __backend_feature_name {
+            () => {{
+                let mut name = "unknown";
+                $(
+                    if cfg!(feature = $name) {
+                        name = $name;
+                    }
+                )*
+                name
+            }};
+        }
+
+        /// Create the device of the backend enabled in the crate expanding the macro and execute
+        /// the bench on it.
+        ///
+        /// Only the backends with their own backend type define a `bench` block, the backends
+        /// enabling the feature of another backend are executed by its block.
+        #[doc(hidden)]
+        #[macro_export]
+        macro_rules! __bench_on_registered_backend {
+            $bench_args => {
+                $($(
+                    #[cfg(feature = $name)]
+                    {
+                        $($bench)*
+                    }
+                )?)*
+            };
+        }
+    };
+}
+
+register_backends! {
+    // The `$fn_name` function is executed on the backend with the float element type `$dtype`,
+    // on the device selected by `$selector`.
+    bench($fn_name:ident, $dtype:ty, $selector:ident);
+    "candle-accelerate" => {
+        features: ["burn/candle", "burn/accelerate"],
+        devices: Cpu,
+        gpus: None,
+        legacy: [],
+        platforms: ["macos"],
+    },
+    "candle-cpu" => {
+        features: ["burn/candle"],
+        devices: Cpu,
+        gpus: None,
+        legacy: [],
+        platforms: [],
+        bench: {
+            use burn::backend::candle::{Candle, CandleDevice};
+
+            $selector.expect_cpu("candle-cpu");
+            let device = CandleDevice::Cpu;
+            $crate::bench_on_backend!($fn_name, Candle<$dtype>, device);
+        },
+    },
+    "candle-cuda" => {
+        features: ["burn/candle-cuda"],
+        devices: Gpu,
+        gpus: Cuda,
+        legacy: [],
+        platforms: [],
+        bench: {
+            use burn::backend::candle::{Candle, CandleDevice};
+
+            let device = CandleDevice::cuda($selector.gpu_index("candle-cuda").unwrap_or(0));
+            $crate::bench_on_backend!($fn_name, Candle<$dtype>, device);
+        },
+    },
+    "candle-metal" => {
+        features: ["burn/candle", "burn/candle-metal"],
+        devices: Gpu,
+        gpus: Single,
+        legacy: [("0.17.0", ["burn/candle", "burn/metal"])],
+        platforms: ["macos"],
+        bench: {
+            use burn::backend::candle::{Candle, CandleDevice};
+
+            $selector.expect_single_gpu("candle-metal");
+            let device = CandleDevice::metal(0);
+            $crate::bench_on_backend!($fn_name, Candle<$dtype>, device);
+        },
+    },
+    "ndarray" => {
+        features: ["burn/ndarray"],
+        devices: Cpu,
+        gpus: None,
+        legacy: [],
+        platforms: [],
+        bench: {
+            use burn::backend::NdArray;
+
+            $selector.expect_cpu("ndarray");
+            let device = Default::default();
+            $crate::bench_on_backend!($fn_name, NdArray<$dtype>, device);
+        },
+    },
+    "ndarray-simd" => {
+        features: ["ndarray", "burn/simd"],
+        devices: Cpu,
+        gpus: None,
+        legacy: [("0.17.0", ["ndarray"])],
+        platforms: [],
+    },
+    "ndarray-blas-accelerate" => {
+        features: ["ndarray", "burn/accelerate"],
+        devices: Cpu,
+        gpus: None,
+        legacy: [],
+        platforms: ["macos"],
+    },
+    "ndarray-blas-netlib" => {
+        features: ["ndarray", "burn/blas-netlib"],
+        devices: Cpu,
+        gpus: None,
+        legacy: [],
+        platforms: [],
+    },
+    "ndarray-blas-openblas" => {
+        features: ["ndarray", "burn/openblas"],
+        devices: Cpu,
+        gpus: None,
+        legacy: [],
+        platforms: [],
+    },
+    "tch-cpu" => {
+        features: ["burn/tch"],
+        devices: Cpu,
+        gpus: None,
+        legacy: [],
+        platforms: [],
+        bench: {
+            use burn::backend::{LibTorch, libtorch::LibTorchDevice};
+
+            $selector.expect_cpu("tch-cpu");
+            let device = LibTorchDevice::Cpu;
+            $crate::bench_on_backend!($fn_name, LibTorch<$dtype>, device);
+        },
+    },
+    "tch-cuda" => {
+        features: ["burn/tch"],
+        devices: Gpu,
+        gpus: Cuda,
+        legacy: [],
+        platforms: [],
+        bench: {
+            use burn::backend::{LibTorch, libtorch::LibTorchDevice};
+
+            let device = LibTorchDevice::Cuda($selector.gpu_index("tch-cuda").unwrap_or(0));
+            $crate::bench_on_backend!($fn_name, LibTorch<$dtype>, device);
+        },
+    },
+    "tch-metal" => {
+        features: ["burn/tch"],
+        devices: Gpu,
+        gpus: Single,
+        legacy: [],
+        platforms: ["macos"],
+        bench: {
+            use burn::backend::{LibTorch, libtorch::LibTorchDevice};
+
+            // MPS only exposes a single device.
+            $selector.expect_single_gpu("tch-metal");
+            let device = LibTorchDevice::Mps;
+            $crate::bench_on_backend!($fn_name, LibTorch<$dtype>, device);
+        },
+    },
+    "wgpu" => {
+        features: ["burn/wgpu", "burn/autotune"],
+        devices: Any,
+        gpus: Adapters,
+        legacy: [],
+        platforms: [],
+        bench: {
+            use burn::backend::Wgpu;
+
+            let device = $crate::wgpu_device!($selector);
+            $crate::bench_on_backend!($fn_name, Wgpu<$dtype>, device);
+        },
+    },
+    "wgpu-fusion" => {
+        features: ["wgpu", "burn/fusion"],
+        devices: Any,
+        gpus: Adapters,
+        legacy: [],
+        platforms: [],
+    },
+    "vulkan" => {
+        features: ["burn/vulkan", "burn/autotune"],
+        devices: Any,
+        gpus: Adapters,
+        legacy: [("0.17.0", ["burn/wgpu-spirv", "burn/autotune"])],
+        platforms: ["linux", "windows"],
+        bench: {
+            #[cfg(target_os = "macos")]
+            panic!("vulkan benchmarks are not supported on macOS, use the wgpu backend instead.");
+
+            #[cfg(not(target_os = "macos"))]
+            {
+                use burn::backend::Wgpu;
+
+                let device = $crate::wgpu_device!($selector);
+                $crate::bench_on_backend!($fn_name, Wgpu<$dtype>, device);
+            }
+        },
+    },
+    "vulkan-fusion" => {
+        features: ["vulkan", "burn/fusion"],
+        devices: Any,
+        gpus: Adapters,
+        legacy: [],
+        platforms: ["linux", "windows"],
+    },
+    "metal" => {
+        features: ["burn/metal", "burn/autotune"],
+        devices: Any,
+        gpus: Adapters,
+        legacy: [("0.17.0", ["burn/wgpu", "burn/autotune"])],
+        platforms: ["macos"],
+        bench: {
+            use burn::backend::Metal;
+
+            let device = $crate::wgpu_device!($selector);
+            $crate::bench_on_backend!($fn_name, Metal<$dtype>, device);
+        },
+    },
+    "metal-fusion" => {
+        features: ["metal", "burn/fusion"],
+        devices: Any,
+        gpus: Adapters,
+        legacy: [],
+        platforms: ["macos"],
+    },
+    "cuda" => {
+        features: ["burn/cuda", "burn/autotune"],
+        devices: Gpu,
+        gpus: Cuda,
+        legacy: [("0.17.0", ["burn/cuda-jit", "burn/autotune"])],
+        platforms: [],
+        bench: {
+            #[cfg(not(feature = "legacy-v16"))]
+            use burn::backend::{Cuda, cuda::CudaDevice};
+            #[cfg(feature = "legacy-v16")]
+            use burn::backend::{CudaJit as Cuda, cuda_jit::CudaDevice};
+
+            let device = match $selector.gpu_index("cuda") {
+                Some(index) => CudaDevice::new(index),
+                None => Default::default(),
+            };
+            $crate::bench_on_backend!($fn_name, Cuda<$dtype>, device);
+        },
+    },
+    "cuda-fusion" => {
+        features: ["cuda", "burn/fusion"],
+        devices: Gpu,
+        gpus: Cuda,
+        legacy: [],
+        platforms: [],
+    },
+    "rocm" => {
+        features: ["burn/rocm", "burn/autotune"],
+        devices: Gpu,
+        gpus: Rocm,
+        legacy: [("0.17.0", ["burn/hip-jit", "burn/autotune"])],
+        platforms: ["linux"],
+        bench: {
+            #[cfg(feature = "legacy-v16")]
+            use burn::backend::{HipJit as Rocm, hip_jit::HipDevice as RocmDevice};
+            #[cfg(not(feature = "legacy-v16"))]
+            use burn::backend::{Rocm, rocm::RocmDevice};
+
+            let device = match $selector.gpu_index("rocm") {
+                Some(index) => RocmDevice::new(index),
+                None => Default::default(),
+            };
+            $crate::bench_on_backend!($fn_name, Rocm<$dtype>, device);
+        },
+    },
+    "rocm-fusion" => {
+        features: ["rocm", "burn/fusion"],
+        devices: Gpu,
+        gpus: Rocm,
+        legacy: [],
+        platforms: ["linux"],
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_names_are_unique() {
+        for (i, backend) in BACKENDS.iter().enumerate() {
+            assert!(
+                BACKENDS[i + 1..].iter().all(|b| b.name != backend.name),
+                "{} is registered twice",
+                backend.name
+            );
+        }
+    }
+
+    #[test]
+    fn backends_are_registered_after_the_backends_they_enable() {
+        for (i, backend) in BACKENDS.iter().enumerate() {
+            for feature in backend.features {
+                if let Some(position) = BACKENDS.iter().position(|b| b.name == *feature) {
+                    assert!(
+                        position < i,
+                        "{} must be registered after {feature}",
+                        backend.name
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn legacy_features_depend_on_the_version() {
+        let cuda = get_backend("cuda").unwrap();
+
+        assert_eq!(
+            cuda.feature_definition(Some(&Version::new(0, 16, 1))),
+            "cuda = [\"burn/cuda-jit\", \"burn/autotune\"]"
+        );
+        assert_eq!(
+            cuda.feature_definition(Some(&Version::new(0, 17, 0))),
+            "cuda = [\"burn/cuda\", \"burn/autotune\"]"
+        );
+        assert_eq!(
+            cuda.feature_definition(None),
+            "cuda = [\"burn/cuda\", \"burn/autotune\"]"
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use std::{pin::Pin, time::Duration};
 
-use crate::{BenchmarkComputations, BenchmarkDurations, BenchmarkResult, TimingMethod};
+use crate::{
+    BenchmarkCase, BenchmarkComputations, BenchmarkDurations, BenchmarkFilter, BenchmarkMemory,
+    BenchmarkMetric, BenchmarkResult, DUAL_TIMING_ENV_VAR, GitProvenance, MemoryProbe,
+    TimingMethod, is_listing_cases,
+};
 
 /// Benchmark trait.
 pub trait Benchmark {
@@ -24,6 +28,39 @@ pub trait Benchmark {
     /// away code that should be benchmarked.
     fn execute(&self, input: Self::Input) -> Self::Output;
 
+    /// How the inputs of the samples are created.
+    fn input_mode(&self) -> InputMode {
+        InputMode::Cloned
+    }
+
+    /// How the durations of the samples are measured.
+    ///
+    /// Defaults to the method selected with the `BURN_BENCH_TIMING_METHOD` environment variable,
+    /// or to the system clock if unset. Device timing requires [profile](Benchmark::profile) to
+    /// measure the device duration, the durations are otherwise recorded as system timings.
+    fn timing_method(&self) -> TimingMethod {
+        TimingMethod::from_env()
+    }
+
+    /// Whether each sample is measured with both the system clock and the device.
+    ///
+    /// The benchmark is then executed twice per sample, once with
+    /// [profile_full](Benchmark::profile_full) and once with [profile](Benchmark::profile), and
+    /// the device durations are recorded alongside the system durations. Defaults to the
+    /// `BURN_BENCH_DUAL_TIMING` environment variable. Batches are only timed with the system clock.
+    fn dual_timing(&self) -> bool {
+        std::env::var(DUAL_TIMING_ENV_VAR).is_ok_and(|v| v == "true")
+    }
+
+    /// Number of executions timed between a single pair of [sync](Benchmark::sync) calls.
+    ///
+    /// The duration of a sample is the duration of the batch divided by its size, which reduces
+    /// the synchronization overhead of very fast operations. Batches are always timed with the
+    /// system clock.
+    fn batch_size(&self) -> usize {
+        1
+    }
+
     /// Number of samples per run required to have a statistical significance.
     fn num_samples(&self) -> usize {
         const DEFAULT: usize = 10;
@@ -50,11 +87,46 @@ pub trait Benchmark {
     /// Wait for computation to complete.
     fn sync(&self);
 
+    /// Called before the warmup executions.
+    fn before_warmup(&self) {}
+
+    /// Called after the warmup executions.
+    fn after_warmup(&self) {}
+
+    /// Called before each measured sample, outside of the measured duration.
+    fn before_sample(&self, _index: usize) {}
+
+    /// Called after each measured sample with its duration, outside of the measured duration.
+    fn after_sample(&self, _index: usize, _duration: Duration) {}
+
+    /// Called once all the samples are executed.
+    fn teardown(&self) {}
+
+    /// Custom metrics of the run, e.g. tokens per second or a cache hit rate, computed once all
+    /// the samples are executed.
+    fn metrics(&self, _durations: &BenchmarkDurations) -> Vec<BenchmarkMetric> {
+        vec![]
+    }
+
     /// Start measuring the computation duration.
     fn profile(&self, args: Self::Input) -> ProfileDuration {
         self.profile_full(args)
     }
 
+    /// Measure the mean duration of the execution of each input, the computations are only
+    /// synchronized before the first and after the last execution.
+    fn profile_batch(&self, inputs: Vec<Self::Input>) -> ProfileDuration {
+        let batch_size = inputs.len().max(1) as u32;
+        self.sync();
+        let start_time = std::time::Instant::now();
+        for input in inputs {
+            let out = self.execute(input);
+            core::mem::drop(out);
+        }
+        self.sync();
+        ProfileDuration::from_duration(start_time.elapsed() / batch_size)
+    }
+
     /// Start measuring the computation duration. Use the full duration irregardless of whether
     /// device duration is available or not.
     fn profile_full(&self, args: Self::Input) -> ProfileDuration {
@@ -66,37 +138,130 @@ pub trait Benchmark {
         ProfileDuration::from_duration(start_time.elapsed())
     }
 
-    /// Run the benchmark a number of times.
-    #[allow(unused_variables)]
+    /// Run the benchmark a number of times and return the durations of the samples.
+    ///
+    /// See [measure](Benchmark::measure) for the memory and the custom metrics.
     fn run(&self, timing_method: TimingMethod) -> BenchmarkDurations {
-        let execute = |args: &Self::Input| {
-            let profile = match timing_method {
-                TimingMethod::System => self.profile_full(args.clone()),
-                TimingMethod::Device => self.profile(args.clone()),
+        self.measure(timing_method).durations
+    }
+
+    /// Run the benchmark a number of times.
+    ///
+    /// The memory used by the process is measured while the samples are executed. The lifecycle
+    /// hooks are called around the warmup and each sample. The returned durations hold the
+    /// timing method that was actually used.
+    fn measure(&self, timing_method: TimingMethod) -> BenchmarkMeasurements {
+        let batch_size = self.batch_size().max(1);
+        let dual_timing = self.dual_timing() && batch_size == 1;
+        let timing_method = if batch_size > 1 || dual_timing {
+            TimingMethod::System
+        } else {
+            timing_method
+        };
+        let num_inputs = if dual_timing { 2 } else { batch_size };
+        let cloned_input = match self.input_mode() {
+            InputMode::Cloned => Some(self.prepare()),
+            InputMode::PerSample => None,
+        };
+        let resolve = |profile: ProfileDuration| {
+            let method = profile.timing_method();
+            (futures_lite::future::block_on(profile.resolve()), method)
+        };
+        // The inputs are created before the measurement starts, the memory is only measured while
+        // they are executed.
+        let execute = |probe: &mut MemoryProbe| {
+            let mut inputs: Vec<Self::Input> = match &cloned_input {
+                Some(args) => vec![args.clone(); num_inputs],
+                None => (0..num_inputs).map(|_| self.prepare()).collect(),
+            };
+            if batch_size > 1 {
+                let (duration, method) = probe.measure(|| resolve(self.profile_batch(inputs)));
+                return (duration, method, None);
+            }
+
+            let device_duration = if dual_timing {
+                let input = inputs.pop().unwrap();
+                let (duration, method) = probe.measure(|| resolve(self.profile(input)));
+                (method == TimingMethod::Device).then_some(duration)
+            } else {
+                None
             };
-            futures_lite::future::block_on(profile.resolve())
+            let args = inputs.pop().unwrap();
+            let (duration, method) = probe.measure(|| match timing_method {
+                TimingMethod::System => resolve(self.profile_full(args)),
+                TimingMethod::Device => resolve(self.profile(args)),
+            });
+            (duration, method, device_duration)
         };
-        let args = self.prepare();
 
         // Warmup
+        self.before_warmup();
+        let mut warmup = MemoryProbe::default();
         for _ in 0..3 {
-            let _duration = execute(&args);
+            let _duration = execute(&mut warmup);
         }
+        self.after_warmup();
         std::thread::sleep(Duration::from_secs(1));
 
         // Real execution.
+        let mut probe = MemoryProbe::default();
+        let mut measured_with = timing_method;
         let mut durations = Vec::with_capacity(self.num_samples());
-        for _ in 0..self.num_samples() {
-            durations.push(execute(&args));
+        let mut device_durations = Vec::with_capacity(self.num_samples());
+        for index in 0..self.num_samples() {
+            self.before_sample(index);
+            let (duration, method, device_duration) = execute(&mut probe);
+            if method == TimingMethod::System {
+                measured_with = TimingMethod::System;
+            }
+            self.after_sample(index, duration);
+            durations.push(duration);
+            device_durations.extend(device_duration);
         }
+        let memory = probe.finish(durations.len());
 
-        BenchmarkDurations {
-            timing_method,
+        // The device durations are only kept when every sample could be measured on the device.
+        if device_durations.len() != durations.len() {
+            device_durations.clear();
+        }
+        let durations = BenchmarkDurations {
+            timing_method: measured_with,
             durations,
+            device_durations,
+        };
+        let metrics = self.metrics(&durations);
+        self.teardown();
+
+        BenchmarkMeasurements {
+            durations,
+            memory,
+            metrics,
         }
     }
 }
 
+/// How the inputs of the samples of a benchmark are created.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    /// The input is prepared once and cloned for every execution.
+    #[default]
+    Cloned,
+    /// A fresh input is prepared for every execution, without being measured. Useful for
+    /// benchmarks consuming or mutating their input.
+    PerSample,
+}
+
+/// Measurements of a benchmark run.
+#[derive(Debug, Default, Clone)]
+pub struct BenchmarkMeasurements {
+    /// Duration of each sample.
+    pub durations: BenchmarkDurations,
+    /// Memory used while executing the samples.
+    pub memory: BenchmarkMemory,
+    /// Custom metrics of the benchmark.
+    pub metrics: Vec<BenchmarkMetric>,
+}
+
 /// Result from profiling between two measurements. This can either be a duration or a future that resolves to a duration.
 pub enum ProfileDuration {
     /// Client profile contains a full duration.
@@ -143,28 +308,244 @@ impl ProfileDuration {
 }
 
 /// Runs the given benchmark on the device and prints result and information.
+///
+/// When the benchmark is excluded by the `BURN_BENCH_FILTER` environment variable, or when
+/// the binary is executed in listing mode, it is not executed and the returned result has no
+/// durations. Such results are skipped when saving.
 pub fn run_benchmark<BM>(benchmark: BM) -> BenchmarkResult
 where
     BM: Benchmark,
 {
+    let case = BenchmarkCase::new(&benchmark);
+    if let Some(filter) = BenchmarkFilter::from_env()
+        && !filter.matches(&case.name, &case.shapes)
+    {
+        return case.skipped();
+    }
+    if is_listing_cases() {
+        case.print();
+        return case.skipped();
+    }
+
     let timestamp = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_millis();
-    let output = std::process::Command::new("git")
-        .args(["rev-parse", "HEAD"])
-        .output()
-        .unwrap();
-    let git_hash = String::from_utf8(output.stdout).unwrap().trim().to_string();
-    let durations = benchmark.run(TimingMethod::System);
+    let provenance = GitProvenance::current();
+    let measurements = benchmark.measure(benchmark.timing_method());
+    let durations = measurements.durations;
 
     BenchmarkResult {
-        raw: durations.clone(),
         computed: BenchmarkComputations::new(&durations),
-        git_hash,
-        name: benchmark.name(),
-        options: benchmark.options(),
-        shapes: benchmark.shapes(),
+        raw: durations,
+        memory: measurements.memory,
+        metrics: measurements.metrics,
+        git_hash: provenance.hash.clone(),
+        git_dirty: provenance.dirty,
+        name: case.name,
+        options: case.options,
+        shapes: case.shapes,
         timestamp,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Benchmark recording the calls to its lifecycle hooks.
+    #[derive(Default)]
+    struct HookBenchmark {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl HookBenchmark {
+        fn push(&self, event: impl Into<String>) {
+            self.events.lock().unwrap().push(event.into());
+        }
+    }
+
+    impl Benchmark for HookBenchmark {
+        type Input = ();
+        type Output = ();
+
+        fn prepare(&self) -> Self::Input {
+            self.push("prepare");
+        }
+
+        fn execute(&self, _input: Self::Input) -> Self::Output {
+            self.push("execute");
+        }
+
+        fn num_samples(&self) -> usize {
+            2
+        }
+
+        fn name(&self) -> String {
+            "hooks".to_string()
+        }
+
+        fn sync(&self) {}
+
+        fn before_warmup(&self) {
+            self.push("before_warmup");
+        }
+
+        fn after_warmup(&self) {
+            self.push("after_warmup");
+        }
+
+        fn before_sample(&self, index: usize) {
+            self.push(format!("before_sample {index}"));
+        }
+
+        fn after_sample(&self, index: usize, _duration: Duration) {
+            self.push(format!("after_sample {index}"));
+        }
+
+        fn teardown(&self) {
+            self.push("teardown");
+        }
+
+        fn metrics(&self, durations: &BenchmarkDurations) -> Vec<BenchmarkMetric> {
+            vec![BenchmarkMetric::new(
+                "samples",
+                durations.durations.len() as f64,
+            )]
+        }
+    }
+
+    #[test]
+    fn lifecycle_hooks_are_called_around_samples() {
+        let benchmark = HookBenchmark::default();
+        let measurements = benchmark.measure(TimingMethod::System);
+
+        assert_eq!(
+            *benchmark.events.lock().unwrap(),
+            vec![
+                "prepare",
+                "before_warmup",
+                "execute",
+                "execute",
+                "execute",
+                "after_warmup",
+                "before_sample 0",
+                "execute",
+                "after_sample 0",
+                "before_sample 1",
+                "execute",
+                "after_sample 1",
+                "teardown",
+            ]
+        );
+        assert_eq!(
+            measurements.metrics,
+            vec![BenchmarkMetric::new("samples", 2.0)]
+        );
+    }
+
+    /// Benchmark counting its prepared inputs and executions.
+    struct CountingBenchmark {
+        input_mode: InputMode,
+        batch_size: usize,
+        dual_timing: bool,
+        prepared: AtomicUsize,
+        executed: AtomicUsize,
+    }
+
+    impl CountingBenchmark {
+        fn new(input_mode: InputMode, batch_size: usize) -> Self {
+            Self {
+                input_mode,
+                batch_size,
+                dual_timing: false,
+                prepared: AtomicUsize::new(0),
+                executed: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl Benchmark for CountingBenchmark {
+        type Input = ();
+        type Output = ();
+
+        fn prepare(&self) -> Self::Input {
+            self.prepared.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn execute(&self, _input: Self::Input) -> Self::Output {
+            self.executed.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn input_mode(&self) -> InputMode {
+            self.input_mode
+        }
+
+        fn batch_size(&self) -> usize {
+            self.batch_size
+        }
+
+        fn dual_timing(&self) -> bool {
+            self.dual_timing
+        }
+
+        fn num_samples(&self) -> usize {
+            5
+        }
+
+        fn name(&self) -> String {
+            "counting".to_string()
+        }
+
+        fn sync(&self) {}
+    }
+
+    #[test]
+    fn inputs_are_prepared_per_sample() {
+        let benchmark = CountingBenchmark::new(InputMode::PerSample, 1);
+        let measurements = benchmark.measure(TimingMethod::System);
+
+        // 3 warmup executions and 5 samples
+        assert_eq!(benchmark.prepared.load(Ordering::Relaxed), 8);
+        assert_eq!(benchmark.executed.load(Ordering::Relaxed), 8);
+        assert_eq!(measurements.durations.durations.len(), 5);
+    }
+
+    #[test]
+    fn batches_execute_several_times_per_sample() {
+        let benchmark = CountingBenchmark::new(InputMode::Cloned, 4);
+        let measurements = benchmark.measure(TimingMethod::Device);
+
+        assert_eq!(benchmark.prepared.load(Ordering::Relaxed), 1);
+        assert_eq!(benchmark.executed.load(Ordering::Relaxed), 8 * 4);
+        assert_eq!(measurements.durations.durations.len(), 5);
+        assert_eq!(measurements.durations.timing_method, TimingMethod::System);
+    }
+
+    #[test]
+    fn device_timing_falls_back_to_system_timing() {
+        let benchmark = CountingBenchmark::new(InputMode::Cloned, 1);
+        let measurements = benchmark.measure(TimingMethod::Device);
+
+        // The benchmark does not measure the device duration.
+        assert_eq!(measurements.durations.timing_method, TimingMethod::System);
+    }
+
+    #[test]
+    fn dual_timing_executes_twice_per_sample() {
+        let benchmark = CountingBenchmark {
+            dual_timing: true,
+            ..CountingBenchmark::new(InputMode::PerSample, 1)
+        };
+        let measurements = benchmark.measure(TimingMethod::Device);
+
+        assert_eq!(benchmark.prepared.load(Ordering::Relaxed), 8 * 2);
+        assert_eq!(benchmark.executed.load(Ordering::Relaxed), 8 * 2);
+        assert_eq!(measurements.durations.durations.len(), 5);
+        assert_eq!(measurements.durations.timing_method, TimingMethod::System);
+        // The device durations are dropped since the benchmark cannot measure them.
+        assert!(measurements.durations.device_durations.is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Benchmark, BenchmarkResult};
+
+/// Environment variable used by the runner to execute the benchmark binaries in listing mode.
+pub const LIST_ENV_VAR: &str = "BURN_BENCH_LIST";
+
+/// Prefix of the lines printed by the benchmark binaries in listing mode.
+pub const LIST_CASE_PREFIX: &str = "burnbench-case: ";
+
+/// Description of a single benchmark case.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BenchmarkCase {
+    /// Name of the benchmark
+    pub name: String,
+    /// Shape dimensions
+    pub shapes: Vec<Vec<usize>>,
+    /// Options passed to the benchmark
+    pub options: Option<String>,
+}
+
+impl BenchmarkCase {
+    /// Describe the given benchmark without executing it.
+    pub fn new<BM: Benchmark>(benchmark: &BM) -> Self {
+        Self {
+            name: benchmark.name(),
+            shapes: benchmark.shapes(),
+            options: benchmark.options(),
+        }
+    }
+
+    /// Result of a case that has not been executed.
+    pub(crate) fn skipped(self) -> BenchmarkResult {
+        BenchmarkResult {
+            name: self.name,
+            options: self.options,
+            shapes: self.shapes,
+            ..Default::default()
+        }
+    }
+
+    /// Print the case so that it can be collected by the runner.
+    pub(crate) fn print(&self) {
+        let case = serde_json::to_string(self).expect("Benchmark case should be serialized");
+        println!("{LIST_CASE_PREFIX}{case}");
+    }
+
+    /// Parse a line printed by a benchmark binary in listing mode.
+    pub fn parse(line: &str) -> Option<Self> {
+        let case = line.trim().strip_prefix(LIST_CASE_PREFIX)?;
+        serde_json::from_str(case).ok()
+    }
+}
+
+/// Returns true if the benchmark binary is executed in listing mode, in which case the
+/// benchmarks are only described and never executed.
+pub fn is_listing_cases() -> bool {
+    std::env::var(LIST_ENV_VAR).is_ok_and(|v| v == "true")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printed_case_can_be_parsed() {
+        let case = BenchmarkCase {
+            name: "matmul-general-float32".to_string(),
+            shapes: vec![vec![8, 1024, 1024]],
+            options: Some("transposed".to_string()),
+        };
+        let line = format!(
+            "{LIST_CASE_PREFIX}{}",
+            serde_json::to_string(&case).unwrap()
+        );
+
+        assert_eq!(BenchmarkCase::parse(&line), Some(case));
+        assert_eq!(BenchmarkCase::parse("Running matmul"), None);
+    }
+}
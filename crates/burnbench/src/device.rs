@@ -0,0 +1,361 @@
+use core::fmt;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::BackendDescriptor;
+use crate::system_info::enumerate_gpu_adapters;
+
+/// Environment variable used by the runner to forward the selected device to the benchmark
+/// binaries.
+pub const DEVICE_ENV_VAR: &str = "BURN_BENCH_DEVICE";
+
+/// Select the device on which a benchmark binary runs.
+///
+/// The selector is written as `kind` or `kind:index`, for instance `cpu`, `discrete:1` or
+/// `integrated:0`. The index defaults to 0 when omitted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceSelector {
+    /// The default device of the backend.
+    #[default]
+    Default,
+    /// The CPU.
+    Cpu,
+    /// The discrete GPU with the given index.
+    Discrete(usize),
+    /// The integrated GPU with the given index.
+    Integrated(usize),
+    /// The virtual GPU with the given index.
+    Virtual(usize),
+}
+
+/// Kind of devices supported by a backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceSupport {
+    /// Only runs on the CPU.
+    Cpu,
+    /// Only runs on GPUs.
+    Gpu,
+    /// Runs on any device.
+    Any,
+}
+
+/// How the GPUs of a backend are enumerated with `--device all`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuEnumeration {
+    /// The backend does not run on GPUs.
+    None,
+    /// The discrete and integrated adapters of the native graphics API, as indexed by wgpu.
+    Adapters,
+    /// The CUDA devices, indexed by their CUDA ordinal.
+    Cuda,
+    /// The HIP devices, indexed by their HIP ordinal.
+    Rocm,
+    /// A single GPU, used through the default device of the backend.
+    Single,
+}
+
+impl DeviceSelector {
+    /// Return the device defined by the `BURN_BENCH_DEVICE` environment variable, or the
+    /// default device if unset.
+    pub fn from_env() -> Self {
+        match std::env::var(DEVICE_ENV_VAR) {
+            Ok(value) => value
+                .parse()
+                .unwrap_or_else(|err| panic!("Invalid device '{value}': {err}")),
+            Err(_) => Self::Default,
+        }
+    }
+
+    /// Returns true if the device can be used with a backend supporting the given kind of
+    /// devices.
+    pub fn is_supported(&self, support: DeviceSupport) -> bool {
+        match (self, support) {
+            (DeviceSelector::Default, _) | (_, DeviceSupport::Any) => true,
+            (DeviceSelector::Cpu, DeviceSupport::Cpu) => true,
+            (DeviceSelector::Cpu, DeviceSupport::Gpu) => false,
+            (_, DeviceSupport::Cpu) => false,
+            (_, DeviceSupport::Gpu) => true,
+        }
+    }
+
+    /// The index of the GPU to use with a GPU only backend, or `None` to use its default device.
+    ///
+    /// These backends index their GPUs by ordinal, which is selected with `discrete:<index>`.
+    ///
+    /// # Panics
+    ///
+    /// If the CPU, an integrated or a virtual GPU is selected.
+    pub fn gpu_index(&self, backend: &str) -> Option<usize> {
+        match self {
+            DeviceSelector::Default => None,
+            DeviceSelector::Discrete(index) => Some(*index),
+            DeviceSelector::Cpu => {
+                panic!("The {self} device is not supported by the {backend} backend.")
+            }
+            DeviceSelector::Integrated(_) | DeviceSelector::Virtual(_) => panic!(
+                "The {self} device is not supported by the {backend} backend, its GPUs are selected with discrete:<index>."
+            ),
+        }
+    }
+
+    /// Check that the device can be used with a backend exposing a single GPU.
+    ///
+    /// # Panics
+    ///
+    /// If a device other than `default` or `discrete:0` is selected.
+    pub fn expect_single_gpu(&self, backend: &str) {
+        if !matches!(self, DeviceSelector::Default | DeviceSelector::Discrete(0)) {
+            panic!(
+                "The {self} device is not supported by the {backend} backend, which only exposes the default device."
+            )
+        }
+    }
+
+    /// The selector recorded with the results, with the default device resolved when the backend
+    /// has a single kind of device: `cpu` for CPU only backends and `discrete:0` for GPU only
+    /// backends indexing their GPUs.
+    pub fn normalized(&self, backend: &BackendDescriptor) -> Self {
+        match (self, backend.devices, backend.gpus) {
+            (DeviceSelector::Default, DeviceSupport::Cpu, _) => DeviceSelector::Cpu,
+            (DeviceSelector::Default, DeviceSupport::Gpu, GpuEnumeration::Cuda)
+            | (DeviceSelector::Default, DeviceSupport::Gpu, GpuEnumeration::Rocm) => {
+                DeviceSelector::Discrete(0)
+            }
+            _ => *self,
+        }
+    }
+
+    /// Check that the device can be used with a CPU only backend.
+    ///
+    /// # Panics
+    ///
+    /// If a GPU is selected.
+    pub fn expect_cpu(&self, backend: &str) {
+        if !self.is_supported(DeviceSupport::Cpu) {
+            panic!("The {self} device is not supported by the {backend} backend.")
+        }
+    }
+}
+
+/// All the devices of this machine the backend can run on: the CPU if supported, followed by
+/// the GPUs enumerated the way the backend indexes them.
+pub(crate) fn available_devices(backend: &BackendDescriptor) -> Vec<DeviceSelector> {
+    let mut devices = vec![];
+    if DeviceSelector::Cpu.is_supported(backend.devices) {
+        devices.push(DeviceSelector::Cpu);
+    }
+
+    match backend.gpus {
+        GpuEnumeration::None => {}
+        GpuEnumeration::Adapters => {
+            let adapters = enumerate_gpu_adapters();
+            let count = |device_type| {
+                adapters
+                    .iter()
+                    .filter(|info| info.device_type == device_type)
+                    .count()
+            };
+            devices.extend((0..count(wgpu::DeviceType::DiscreteGpu)).map(DeviceSelector::Discrete));
+            devices.extend(
+                (0..count(wgpu::DeviceType::IntegratedGpu)).map(DeviceSelector::Integrated),
+            );
+        }
+        GpuEnumeration::Cuda => {
+            devices.extend((0..cuda_device_count()).map(DeviceSelector::Discrete));
+        }
+        GpuEnumeration::Rocm => {
+            devices.extend((0..rocm_device_count()).map(DeviceSelector::Discrete));
+        }
+        GpuEnumeration::Single => devices.push(DeviceSelector::Default),
+    }
+
+    devices
+}
+
+/// Number of CUDA devices, restricted by `CUDA_VISIBLE_DEVICES` when set.
+fn cuda_device_count() -> usize {
+    if let Some(count) = visible_device_count("CUDA_VISIBLE_DEVICES") {
+        return count;
+    }
+
+    Command::new("nvidia-smi")
+        .args(["--query-gpu=index", "--format=csv,noheader"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+/// Number of HIP devices, restricted by `HIP_VISIBLE_DEVICES` when set.
+///
+/// The GPUs are the nodes of the KFD topology with compute units, the CPU nodes have none.
+fn rocm_device_count() -> usize {
+    if let Some(count) = visible_device_count("HIP_VISIBLE_DEVICES") {
+        return count;
+    }
+
+    let Ok(nodes) = std::fs::read_dir(Path::new("/sys/class/kfd/kfd/topology/nodes")) else {
+        return 0;
+    };
+    nodes
+        .filter_map(|node| node.ok())
+        .filter_map(|node| std::fs::read_to_string(node.path().join("properties")).ok())
+        .filter(|properties| is_kfd_gpu_node(properties))
+        .count()
+}
+
+/// Number of devices listed in the given environment variable, `None` if it is unset.
+fn visible_device_count(env_var: &str) -> Option<usize> {
+    std::env::var(env_var)
+        .ok()
+        .map(|devices| parse_visible_devices(&devices))
+}
+
+/// Number of devices of a `*_VISIBLE_DEVICES` list, the devices after an invalid entry are
+/// ignored by the drivers.
+fn parse_visible_devices(devices: &str) -> usize {
+    devices
+        .split(',')
+        .map(str::trim)
+        .take_while(|device| !device.is_empty() && !device.starts_with('-'))
+        .count()
+}
+
+fn is_kfd_gpu_node(properties: &str) -> bool {
+    properties.lines().any(|line| {
+        line.strip_prefix("simd_count ")
+            .and_then(|count| count.trim().parse::<usize>().ok())
+            .is_some_and(|count| count > 0)
+    })
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceSelector::Default => f.write_str("default"),
+            DeviceSelector::Cpu => f.write_str("cpu"),
+            DeviceSelector::Discrete(index) => write!(f, "discrete:{index}"),
+            DeviceSelector::Integrated(index) => write!(f, "integrated:{index}"),
+            DeviceSelector::Virtual(index) => write!(f, "virtual:{index}"),
+        }
+    }
+}
+
+impl FromStr for DeviceSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, index) = match s.split_once(':') {
+            Some((kind, index)) => {
+                let index = index
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid device index '{index}'"))?;
+                (kind, Some(index))
+            }
+            None => (s, None),
+        };
+
+        match (kind, index) {
+            ("default", None) => Ok(DeviceSelector::Default),
+            ("cpu", None) => Ok(DeviceSelector::Cpu),
+            ("discrete", index) => Ok(DeviceSelector::Discrete(index.unwrap_or(0))),
+            ("integrated", index) => Ok(DeviceSelector::Integrated(index.unwrap_or(0))),
+            ("virtual", index) => Ok(DeviceSelector::Virtual(index.unwrap_or(0))),
+            ("default" | "cpu", Some(_)) => Err(format!("the {kind} device has no index")),
+            _ => Err(format!(
+                "unknown device kind '{kind}', expected one of: default, cpu, discrete, integrated, virtual"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_device_selectors() {
+        assert_eq!("cpu".parse(), Ok(DeviceSelector::Cpu));
+        assert_eq!("default".parse(), Ok(DeviceSelector::Default));
+        assert_eq!("discrete".parse(), Ok(DeviceSelector::Discrete(0)));
+        assert_eq!("discrete:1".parse(), Ok(DeviceSelector::Discrete(1)));
+        assert_eq!("integrated:2".parse(), Ok(DeviceSelector::Integrated(2)));
+        assert!("cpu:1".parse::<DeviceSelector>().is_err());
+        assert!("discrete:x".parse::<DeviceSelector>().is_err());
+        assert!("tpu".parse::<DeviceSelector>().is_err());
+    }
+
+    #[test]
+    fn display_roundtrip() {
+        for device in [
+            DeviceSelector::Default,
+            DeviceSelector::Cpu,
+            DeviceSelector::Discrete(3),
+            DeviceSelector::Integrated(0),
+            DeviceSelector::Virtual(1),
+        ] {
+            assert_eq!(device.to_string().parse(), Ok(device));
+        }
+    }
+
+    #[test]
+    fn device_support() {
+        assert!(DeviceSelector::Default.is_supported(DeviceSupport::Cpu));
+        assert!(DeviceSelector::Cpu.is_supported(DeviceSupport::Any));
+        assert!(!DeviceSelector::Cpu.is_supported(DeviceSupport::Gpu));
+        assert!(!DeviceSelector::Discrete(0).is_supported(DeviceSupport::Cpu));
+        assert!(DeviceSelector::Integrated(1).is_supported(DeviceSupport::Gpu));
+    }
+
+    #[test]
+    fn gpu_only_backends_select_their_gpus_by_ordinal() {
+        assert_eq!(DeviceSelector::Default.gpu_index("cuda"), None);
+        assert_eq!(DeviceSelector::Discrete(1).gpu_index("cuda"), Some(1));
+        let backend = |name| crate::get_backend(name).unwrap();
+        assert_eq!(
+            DeviceSelector::Default.normalized(backend("cuda-fusion")),
+            DeviceSelector::Discrete(0)
+        );
+        assert_eq!(
+            DeviceSelector::Default.normalized(backend("ndarray")),
+            DeviceSelector::Cpu
+        );
+        assert_eq!(
+            DeviceSelector::Default.normalized(backend("wgpu")),
+            DeviceSelector::Default
+        );
+        assert_eq!(
+            DeviceSelector::Integrated(1).normalized(backend("wgpu")),
+            DeviceSelector::Integrated(1)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "integrated:0 device is not supported by the cuda backend")]
+    fn gpu_only_backends_reject_integrated_gpus() {
+        DeviceSelector::Integrated(0).gpu_index("cuda");
+    }
+
+    #[test]
+    #[should_panic(expected = "discrete:1 device is not supported by the tch-metal backend")]
+    fn single_gpu_backends_reject_other_gpus() {
+        DeviceSelector::Discrete(0).expect_single_gpu("tch-metal");
+        DeviceSelector::Discrete(1).expect_single_gpu("tch-metal");
+    }
+
+    #[test]
+    fn visible_devices_are_counted() {
+        assert_eq!(parse_visible_devices("0,2"), 2);
+        assert_eq!(parse_visible_devices(""), 0);
+        assert_eq!(parse_visible_devices("1,-1,0"), 1);
+        assert!(is_kfd_gpu_node("cpu_cores_count 0\nsimd_count 256\n"));
+        assert!(!is_kfd_gpu_node("cpu_cores_count 16\nsimd_count 0\n"));
+    }
+}
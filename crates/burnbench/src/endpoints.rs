@@ -0,0 +1,230 @@
+use serde::Deserialize;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::data_dir;
+
+/// Environment variable overriding the base URL of the benchmark server.
+pub const SERVER_URL_ENV_VAR: &str = "BURN_BENCH_SERVER_URL";
+/// Environment variable overriding the base URL of the website displaying the shared results.
+pub const WEBSITE_URL_ENV_VAR: &str = "BURN_BENCH_WEBSITE_URL";
+
+/// Name of the configuration file in the data directory.
+const CONFIG_FILE: &str = "config.toml";
+
+const DEFAULT_SERVER_URL: &str = if cfg!(debug_assertions) {
+    // development
+    "http://localhost:8000/v1/"
+} else {
+    // production
+    "https://user-benchmark-server-812794505978.northamerica-northeast1.run.app/v1/"
+};
+
+const DEFAULT_WEBSITE_URL: &str = if cfg!(debug_assertions) || cfg!(test) {
+    "http://localhost:4321/"
+} else {
+    "https://burn.dev/"
+};
+
+/// Endpoints given with the `--server-url` and `--website-url` arguments of the runner.
+static OVERRIDES: OnceLock<(Option<String>, Option<String>)> = OnceLock::new();
+
+/// Override the endpoints of the current process.
+pub(crate) fn set_endpoints(server_url: Option<String>, website_url: Option<String>) {
+    OVERRIDES.set((server_url, website_url)).ok();
+}
+
+/// Path of the configuration file, `config.toml` in the data directory.
+pub fn config_file() -> PathBuf {
+    data_dir().join(CONFIG_FILE)
+}
+
+/// Where the URL of an endpoint comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointSource {
+    /// Argument of the command line.
+    Argument,
+    /// Environment variable with the given name.
+    Env(&'static str),
+    /// Configuration file at the given path.
+    ConfigFile(PathBuf),
+    /// Default of the build, localhost for debug builds.
+    Default,
+}
+
+/// URL of a server used by burnbench, ending with a slash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub url: String,
+    pub source: EndpointSource,
+}
+
+/// The servers used to share the results.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoints {
+    /// Benchmark server receiving the results, the webhooks and handling the authentication.
+    pub server: Endpoint,
+    /// Website displaying the shared results.
+    pub website: Endpoint,
+}
+
+/// Content of the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Config {
+    endpoints: EndpointsConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct EndpointsConfig {
+    server_url: Option<String>,
+    website_url: Option<String>,
+}
+
+impl Endpoints {
+    /// The endpoints of the current process, given by the arguments of the runner, then the
+    /// environment variables, then the configuration file, then the defaults of the build.
+    ///
+    /// They are resolved once per process.
+    pub fn current() -> &'static Self {
+        static CURRENT: OnceLock<Endpoints> = OnceLock::new();
+        CURRENT.get_or_init(|| {
+            let (server_url, website_url) = OVERRIDES.get().cloned().unwrap_or_default();
+            let path = config_file();
+            let config = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| match toml::from_str::<Config>(&content) {
+                    Ok(config) => Some(config),
+                    Err(err) => {
+                        eprintln!(
+                            "⚠️ Ignoring invalid configuration {} ({err})",
+                            path.display()
+                        );
+                        None
+                    }
+                })
+                .unwrap_or_default();
+
+            Self {
+                server: Endpoint::resolve(
+                    server_url,
+                    SERVER_URL_ENV_VAR,
+                    config.endpoints.server_url.map(|url| (url, path.clone())),
+                    DEFAULT_SERVER_URL,
+                ),
+                website: Endpoint::resolve(
+                    website_url,
+                    WEBSITE_URL_ENV_VAR,
+                    config.endpoints.website_url.map(|url| (url, path)),
+                    DEFAULT_WEBSITE_URL,
+                ),
+            }
+        })
+    }
+
+    /// URL of the given route of the benchmark server.
+    pub fn server_url(&self, route: &str) -> String {
+        format!("{}{route}", self.server.url)
+    }
+
+    /// URL of the given page of the website.
+    pub fn website_url(&self, page: &str) -> String {
+        format!("{}{page}", self.website.url)
+    }
+}
+
+impl Endpoint {
+    fn resolve(
+        argument: Option<String>,
+        env_var: &'static str,
+        config: Option<(String, PathBuf)>,
+        default: &str,
+    ) -> Self {
+        let env = std::env::var(env_var).ok().filter(|url| !url.is_empty());
+        let (url, source) = if let Some(url) = argument {
+            (url, EndpointSource::Argument)
+        } else if let Some(url) = env {
+            (url, EndpointSource::Env(env_var))
+        } else if let Some((url, path)) = config {
+            (url, EndpointSource::ConfigFile(path))
+        } else {
+            (default.to_string(), EndpointSource::Default)
+        };
+
+        Self {
+            url: with_trailing_slash(url),
+            source,
+        }
+    }
+}
+
+impl Display for EndpointSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Argument => f.write_str("command line"),
+            Self::Env(name) => write!(f, "{name}"),
+            Self::ConfigFile(path) => write!(f, "{}", path.display()),
+            Self::Default if cfg!(debug_assertions) => f.write_str("default of debug builds"),
+            Self::Default => f.write_str("default"),
+        }
+    }
+}
+
+/// The routes are appended to the base URLs, which must end with a slash.
+fn with_trailing_slash(mut url: String) -> String {
+    if !url.ends_with('/') {
+        url.push('/');
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_take_precedence_over_the_configuration() {
+        let endpoint = Endpoint::resolve(
+            Some("http://bench.internal:8000/v1".to_string()),
+            "BURN_BENCH_TEST_UNSET_URL",
+            Some(("http://other/".to_string(), PathBuf::from("config.toml"))),
+            DEFAULT_SERVER_URL,
+        );
+
+        assert_eq!(endpoint.url, "http://bench.internal:8000/v1/");
+        assert_eq!(endpoint.source, EndpointSource::Argument);
+    }
+
+    #[test]
+    fn configuration_file_takes_precedence_over_the_default() {
+        let config: Config =
+            toml::from_str("[endpoints]\nwebsite-url = \"https://bench.internal/\"").unwrap();
+        let endpoint = Endpoint::resolve(
+            None,
+            "BURN_BENCH_TEST_UNSET_URL",
+            config
+                .endpoints
+                .website_url
+                .map(|url| (url, PathBuf::from("config.toml"))),
+            DEFAULT_WEBSITE_URL,
+        );
+
+        assert_eq!(endpoint.url, "https://bench.internal/");
+        assert_eq!(
+            endpoint.source,
+            EndpointSource::ConfigFile(PathBuf::from("config.toml"))
+        );
+        assert_eq!(config.endpoints.server_url, None);
+    }
+
+    #[test]
+    fn default_endpoints_are_used_without_configuration() {
+        let endpoint =
+            Endpoint::resolve(None, "BURN_BENCH_TEST_UNSET_URL", None, DEFAULT_SERVER_URL);
+
+        assert_eq!(endpoint.url, DEFAULT_SERVER_URL);
+        assert_eq!(endpoint.source, EndpointSource::Default);
+    }
+}
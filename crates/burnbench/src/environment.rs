@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Environment variable used by the runner to forward the pre-flight checks to the benchmark
+/// binaries.
+pub const ENVIRONMENT_ENV_VAR: &str = "BURN_BENCH_ENVIRONMENT";
+
+/// Maximum 1-minute load average per logical core before the machine is considered busy.
+const MAX_LOAD_PER_CORE: f64 = 0.1;
+/// Minimum ratio of available memory before the machine is considered under memory pressure.
+const MIN_AVAILABLE_MEMORY_RATIO: f64 = 0.2;
+
+/// Result of the checks of the sources of noise of the machine running the benchmarks.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EnvironmentChecks {
+    pub checks: Vec<EnvironmentCheck>,
+}
+
+/// Result of a single environment check.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvironmentCheck {
+    /// Name of the check.
+    pub name: String,
+    /// Outcome of the check.
+    pub status: CheckStatus,
+    /// Value read from the system, if any.
+    pub value: Option<String>,
+    /// Explanation of the warning.
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckStatus {
+    /// The machine is not expected to add noise to the measurements.
+    Passed,
+    /// The machine is likely to add noise to the measurements.
+    Warning,
+    /// The value cannot be read on this platform.
+    Unavailable,
+}
+
+impl EnvironmentChecks {
+    /// Inspect the CPU governor, the turbo state, the load average, the available memory and the
+    /// power supply of the machine.
+    pub fn run() -> Self {
+        Self {
+            checks: vec![
+                check_governor(),
+                check_turbo(),
+                check_load_average(),
+                check_available_memory(),
+                check_power_supply(),
+            ],
+        }
+    }
+
+    /// Return the checks forwarded by the runner with the `BURN_BENCH_ENVIRONMENT` environment
+    /// variable, or run them if unset.
+    pub fn from_env() -> Self {
+        std::env::var(ENVIRONMENT_ENV_VAR)
+            .ok()
+            .and_then(|checks| serde_json::from_str(&checks).ok())
+            .unwrap_or_else(Self::run)
+    }
+
+    /// The checks that reported a warning.
+    pub fn warnings(&self) -> impl Iterator<Item = &EnvironmentCheck> {
+        self.checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Warning)
+    }
+
+    /// Returns true if none of the checks reported a warning.
+    pub fn is_clean(&self) -> bool {
+        self.warnings().next().is_none()
+    }
+}
+
+impl EnvironmentCheck {
+    fn new(
+        name: &str,
+        status: CheckStatus,
+        value: Option<String>,
+        message: Option<String>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            value,
+            message,
+        }
+    }
+
+    fn unavailable(name: &str) -> Self {
+        Self::new(name, CheckStatus::Unavailable, None, None)
+    }
+}
+
+fn read_file<P: AsRef<Path>>(path: P) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_string())
+}
+
+fn check_governor() -> EnvironmentCheck {
+    let name = "cpuGovernor";
+    let Ok(entries) = fs::read_dir("/sys/devices/system/cpu") else {
+        return EnvironmentCheck::unavailable(name);
+    };
+
+    let mut governors = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| read_file(entry.path().join("cpufreq/scaling_governor")))
+        .collect::<Vec<_>>();
+    governors.sort();
+    governors.dedup();
+
+    if governors.is_empty() {
+        return EnvironmentCheck::unavailable(name);
+    }
+
+    let value = governors.join(",");
+    if governors.iter().all(|governor| governor == "performance") {
+        EnvironmentCheck::new(name, CheckStatus::Passed, Some(value), None)
+    } else {
+        EnvironmentCheck::new(
+            name,
+            CheckStatus::Warning,
+            Some(value),
+            Some("the CPU frequency scales with the load, use the performance governor".into()),
+        )
+    }
+}
+
+fn check_turbo() -> EnvironmentCheck {
+    let name = "turbo";
+    let enabled = match (
+        read_file("/sys/devices/system/cpu/intel_pstate/no_turbo"),
+        read_file("/sys/devices/system/cpu/cpufreq/boost"),
+    ) {
+        (Some(no_turbo), _) => no_turbo == "0",
+        (None, Some(boost)) => boost == "1",
+        (None, None) => return EnvironmentCheck::unavailable(name),
+    };
+
+    if enabled {
+        EnvironmentCheck::new(
+            name,
+            CheckStatus::Warning,
+            Some("enabled".into()),
+            Some("the CPU frequency depends on its temperature, disable turbo boost".into()),
+        )
+    } else {
+        EnvironmentCheck::new(name, CheckStatus::Passed, Some("disabled".into()), None)
+    }
+}
+
+fn check_load_average() -> EnvironmentCheck {
+    let name = "loadAverage";
+    let Some(load) = read_file("/proc/loadavg").and_then(|content| parse_load_average(&content))
+    else {
+        return EnvironmentCheck::unavailable(name);
+    };
+
+    let cores = std::thread::available_parallelism()
+        .map(|cores| cores.get())
+        .unwrap_or(1);
+    let value = Some(format!("{load:.2}"));
+
+    if load / cores as f64 > MAX_LOAD_PER_CORE {
+        EnvironmentCheck::new(
+            name,
+            CheckStatus::Warning,
+            value,
+            Some("other processes are running, close them before benchmarking".into()),
+        )
+    } else {
+        EnvironmentCheck::new(name, CheckStatus::Passed, value, None)
+    }
+}
+
+fn check_available_memory() -> EnvironmentCheck {
+    let name = "availableMemory";
+    let Some((total, available)) =
+        read_file("/proc/meminfo").and_then(|content| parse_meminfo(&content))
+    else {
+        return EnvironmentCheck::unavailable(name);
+    };
+
+    let ratio = available as f64 / total as f64;
+    let value = Some(format!("{}/{} MiB", available / 1024, total / 1024));
+
+    if ratio < MIN_AVAILABLE_MEMORY_RATIO {
+        EnvironmentCheck::new(
+            name,
+            CheckStatus::Warning,
+            value,
+            Some("the memory is almost full, the system may swap".into()),
+        )
+    } else {
+        EnvironmentCheck::new(name, CheckStatus::Passed, value, None)
+    }
+}
+
+fn check_power_supply() -> EnvironmentCheck {
+    let name = "powerSupply";
+    let Ok(entries) = fs::read_dir("/sys/class/power_supply") else {
+        return EnvironmentCheck::unavailable(name);
+    };
+
+    let mut has_battery = false;
+    let mut on_mains = false;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        match read_file(path.join("type")).as_deref() {
+            Some("Battery") => has_battery = true,
+            Some("Mains") => on_mains |= read_file(path.join("online")).as_deref() == Some("1"),
+            _ => {}
+        }
+    }
+
+    if has_battery && !on_mains {
+        EnvironmentCheck::new(
+            name,
+            CheckStatus::Warning,
+            Some("battery".into()),
+            Some("the machine runs on battery, plug it in".into()),
+        )
+    } else {
+        let value = if has_battery { "mains" } else { "no battery" };
+        EnvironmentCheck::new(name, CheckStatus::Passed, Some(value.into()), None)
+    }
+}
+
+/// Parse the 1-minute load average from the content of `/proc/loadavg`.
+fn parse_load_average(content: &str) -> Option<f64> {
+    content.split_whitespace().next()?.parse().ok()
+}
+
+/// Parse the total and available memory in kB from the content of `/proc/meminfo`.
+fn parse_meminfo(content: &str) -> Option<(u64, u64)> {
+    let value = |key: &str| {
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))?
+            .split_whitespace()
+            .next()?
+            .parse::<u64>()
+            .ok()
+    };
+
+    Some((value("MemTotal")?, value("MemAvailable")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_proc_files() {
+        assert_eq!(
+            parse_load_average("0.52 0.58 0.59 1/467 12345\n"),
+            Some(0.52)
+        );
+        assert_eq!(parse_load_average(""), None);
+
+        let meminfo = "MemTotal:       16303428 kB\nMemFree:         1234567 kB\nMemAvailable:    8151714 kB\n";
+        assert_eq!(parse_meminfo(meminfo), Some((16303428, 8151714)));
+        assert_eq!(parse_meminfo("MemTotal: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn checks_roundtrip_through_json() {
+        let checks = EnvironmentChecks {
+            checks: vec![
+                EnvironmentCheck::new("turbo", CheckStatus::Passed, Some("disabled".into()), None),
+                EnvironmentCheck::new(
+                    "loadAverage",
+                    CheckStatus::Warning,
+                    Some("3.20".into()),
+                    Some("busy".into()),
+                ),
+            ],
+        };
+
+        let json = serde_json::to_string(&checks).unwrap();
+        assert!(json.starts_with('['));
+        let parsed: EnvironmentChecks = serde_json::from_str(&json).unwrap();
+
+        assert!(!parsed.is_clean());
+        assert_eq!(parsed.warnings().count(), 1);
+        assert_eq!(parsed.checks[0].status, CheckStatus::Passed);
+    }
+}
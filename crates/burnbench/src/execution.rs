@@ -0,0 +1,337 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Environment variable used by the runner to forward the execution settings to the benchmark
+/// binaries.
+pub const EXECUTION_ENV_VAR: &str = "BURN_BENCH_EXECUTION";
+
+/// Environment variables controlling the size of the thread pools of the CPU backends and of the
+/// BLAS libraries they use.
+const THREAD_ENV_VARS: [&str; 5] = [
+    "RAYON_NUM_THREADS",
+    "OMP_NUM_THREADS",
+    "OPENBLAS_NUM_THREADS",
+    "MKL_NUM_THREADS",
+    "VECLIB_MAXIMUM_THREADS",
+];
+
+/// Placement and scheduling of the benchmark processes.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ExecutionSettings {
+    /// CPUs the benchmark process is pinned to, in the `taskset` list format, e.g. `0-3,8`.
+    pub cpu_set: Option<String>,
+    /// Number of threads of the CPU backends.
+    pub threads: Option<usize>,
+    /// Niceness of the benchmark process, negative values raise its priority.
+    pub nice: Option<i32>,
+}
+
+impl ExecutionSettings {
+    /// Return the settings applied by the runner, read from the `BURN_BENCH_EXECUTION`
+    /// environment variable.
+    pub fn from_env() -> Self {
+        std::env::var(EXECUTION_ENV_VAR)
+            .ok()
+            .and_then(|settings| serde_json::from_str(&settings).ok())
+            .unwrap_or_default()
+    }
+
+    /// Return the settings applied to the current process: the settings of the runner with the
+    /// CPU set and the niceness read back from the kernel when the runner set them.
+    pub fn applied() -> Self {
+        let mut settings = Self::from_env();
+        if settings.cpu_set.is_some() {
+            settings.cpu_set = std::fs::read_to_string("/proc/self/status")
+                .ok()
+                .and_then(|status| parse_cpus_allowed(&status));
+        }
+        if settings.nice.is_some() {
+            settings.nice = std::fs::read_to_string("/proc/self/stat")
+                .ok()
+                .and_then(|stat| parse_nice(&stat));
+        }
+        settings
+    }
+
+    /// Check that the settings can be applied on this platform.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(cpu_set) = &self.cpu_set {
+            parse_cpu_set(cpu_set)?;
+        }
+        if self.threads == Some(0) {
+            return Err("the number of threads must be at least 1".to_string());
+        }
+        if (self.cpu_set.is_some() || self.nice.is_some()) && !cfg!(target_os = "linux") {
+            return Err("CPU pinning and priority are only supported on Linux".to_string());
+        }
+        if self.runner().is_some() {
+            configured_runner()?;
+        }
+
+        Ok(())
+    }
+
+    /// Environment variables applying the settings to the benchmark binaries executed by
+    /// `cargo bench`.
+    pub(crate) fn envs(&self) -> Vec<(String, String)> {
+        let mut envs = vec![(
+            EXECUTION_ENV_VAR.to_string(),
+            serde_json::to_string(self).unwrap(),
+        )];
+
+        if let Some(threads) = self.threads {
+            envs.extend(
+                THREAD_ENV_VARS
+                    .iter()
+                    .map(|var| (var.to_string(), threads.to_string())),
+            );
+        }
+
+        // The runner only wraps the benchmark binaries, not the compilation. The runner
+        // configured by the user, checked by `validate`, is executed by ours.
+        if let Some(mut runner) = self.runner() {
+            if let Ok(Some(configured)) = configured_runner() {
+                runner = format!("{runner} {configured}");
+            }
+            envs.push((runner_env_var(), runner));
+        }
+
+        envs
+    }
+
+    /// The command wrapping the benchmark binaries, if any.
+    fn runner(&self) -> Option<String> {
+        let mut command = vec![];
+        if let Some(cpu_set) = &self.cpu_set {
+            command.push(format!("taskset --cpu-list {cpu_set}"));
+        }
+        if let Some(nice) = self.nice {
+            command.push(format!("nice -n {nice}"));
+        }
+
+        if command.is_empty() {
+            None
+        } else {
+            Some(command.join(" "))
+        }
+    }
+}
+
+/// Environment variable overriding the runner of the host target.
+fn runner_env_var() -> String {
+    let target = env!("BURNBENCH_TARGET").to_uppercase().replace('-', "_");
+    format!("CARGO_TARGET_{target}_RUNNER")
+}
+
+/// The runner of the host target configured by the user, either with the
+/// `CARGO_TARGET_<TRIPLE>_RUNNER` environment variable or in the cargo configuration files read
+/// by `cargo bench`.
+///
+/// Fails when a runner is configured for a `cfg(...)` expression, which is not resolved here.
+fn configured_runner() -> Result<Option<String>, String> {
+    if let Ok(runner) = std::env::var(runner_env_var()) {
+        return Ok(Some(runner).filter(|runner| !runner.trim().is_empty()));
+    }
+
+    for path in cargo_config_files() {
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let config = toml::from_str::<toml::Table>(&content)
+            .map_err(|err| format!("invalid cargo configuration {} ({err})", path.display()))?;
+        if let Some(runner) = runner_from_config(&config, env!("BURNBENCH_TARGET"))
+            .map_err(|err| format!("{err} in {}", path.display()))?
+        {
+            return Ok(Some(runner));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The cargo configuration files, by order of precedence: the `.cargo` directories of the
+/// current directory and of its parents, then the cargo home.
+fn cargo_config_files() -> Vec<PathBuf> {
+    let mut dirs = std::env::current_dir()
+        .map(|dir| {
+            dir.ancestors()
+                .map(|dir| dir.join(".cargo"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if let Some(cargo_home) = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")))
+    {
+        dirs.push(cargo_home);
+    }
+
+    let mut files = vec![];
+    for dir in dirs {
+        for name in ["config.toml", "config"] {
+            let path = dir.join(name);
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// The runner of the given target in a cargo configuration, given as a command line or as a
+/// list of arguments.
+fn runner_from_config(config: &toml::Table, target: &str) -> Result<Option<String>, String> {
+    let Some(targets) = config.get("target").and_then(|targets| targets.as_table()) else {
+        return Ok(None);
+    };
+    if let Some(key) = targets.iter().find_map(|(key, value)| {
+        (key.starts_with("cfg(") && value.get("runner").is_some()).then_some(key)
+    }) {
+        return Err(format!(
+            "the runner configured for `{key}` cannot be combined with --cpu-set and --nice, configure it for `{target}` instead"
+        ));
+    }
+
+    match targets.get(target).and_then(|target| target.get("runner")) {
+        None => Ok(None),
+        Some(toml::Value::String(runner)) => Ok(Some(runner.clone())),
+        Some(toml::Value::Array(args)) => args
+            .iter()
+            .map(|arg| {
+                arg.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| format!("invalid runner of `{target}`"))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|args| Some(args.join(" "))),
+        Some(_) => Err(format!("invalid runner of `{target}`")),
+    }
+}
+
+/// The CPUs the process is allowed to run on, from the `Cpus_allowed_list` of
+/// `/proc/<pid>/status`.
+fn parse_cpus_allowed(status: &str) -> Option<String> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+        .map(|cpus| cpus.trim().to_string())
+}
+
+/// The niceness of the process, the 19th field of `/proc/<pid>/stat`. The fields are counted
+/// after the command name, which can contain spaces.
+fn parse_nice(stat: &str) -> Option<i32> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(16)?.parse().ok()
+}
+
+/// Parse a list of CPUs in the `taskset` list format, e.g. `0-3,8`.
+pub fn parse_cpu_set(cpu_set: &str) -> Result<Vec<usize>, String> {
+    let parse = |cpu: &str| {
+        cpu.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("invalid CPU '{cpu}' in the CPU set '{cpu_set}'"))
+    };
+
+    let mut cpus = vec![];
+    for range in cpu_set.split(',') {
+        match range.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if first > last {
+                    return Err(format!("invalid CPU range '{range}'"));
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(parse(range)?),
+        }
+    }
+
+    Ok(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cpu_sets() {
+        assert_eq!(parse_cpu_set("0-3,8"), Ok(vec![0, 1, 2, 3, 8]));
+        assert_eq!(parse_cpu_set("5"), Ok(vec![5]));
+        assert!(parse_cpu_set("3-1").is_err());
+        assert!(parse_cpu_set("a").is_err());
+    }
+
+    #[test]
+    fn settings_are_applied_with_env_vars() {
+        let settings = ExecutionSettings {
+            cpu_set: Some("0-3".to_string()),
+            threads: Some(4),
+            nice: Some(-5),
+        };
+        let envs = settings.envs();
+        let get = |key: &str| envs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+
+        assert_eq!(get("RAYON_NUM_THREADS"), Some("4"));
+        assert_eq!(get("OPENBLAS_NUM_THREADS"), Some("4"));
+        assert!(
+            envs.iter()
+                .any(|(k, v)| k.ends_with("_RUNNER") && v == "taskset --cpu-list 0-3 nice -n -5")
+        );
+        let forwarded: ExecutionSettings =
+            serde_json::from_str(get(EXECUTION_ENV_VAR).unwrap()).unwrap();
+        assert_eq!(forwarded, settings);
+    }
+
+    #[test]
+    fn configured_runners_are_read_from_the_cargo_configuration() {
+        let config = |content: &str| toml::from_str::<toml::Table>(content).unwrap();
+        let target = "x86_64-unknown-linux-gnu";
+
+        assert_eq!(
+            runner_from_config(
+                &config("[target.x86_64-unknown-linux-gnu]\nrunner = \"valgrind -q\""),
+                target
+            ),
+            Ok(Some("valgrind -q".to_string()))
+        );
+        assert_eq!(
+            runner_from_config(
+                &config("[target.x86_64-unknown-linux-gnu]\nrunner = [\"perf\", \"stat\"]"),
+                target
+            ),
+            Ok(Some("perf stat".to_string()))
+        );
+        assert_eq!(
+            runner_from_config(
+                &config("[target.aarch64-apple-darwin]\nrunner = \"x\""),
+                target
+            ),
+            Ok(None)
+        );
+        assert!(
+            runner_from_config(
+                &config("[target.'cfg(target_os = \"linux\")']\nrunner = \"x\""),
+                target
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn applied_settings_are_read_from_the_kernel() {
+        let status = "Name:\tbench\nCpus_allowed:\tf\nCpus_allowed_list:\t0-3\n";
+        let stat = "4242 (bench (1)) R 1 4242 4242 0 -1 4194304 100 0 0 0 5 1 0 0 25 5 1 0 100";
+
+        assert_eq!(parse_cpus_allowed(status), Some("0-3".to_string()));
+        assert_eq!(parse_nice(stat), Some(5));
+        assert_eq!(parse_nice("4242 (bench"), None);
+    }
+
+    #[test]
+    fn default_settings_do_not_wrap_the_binaries() {
+        let envs = ExecutionSettings::default().envs();
+        assert_eq!(envs.len(), 1);
+        assert_eq!(envs[0].0, EXECUTION_ENV_VAR);
+    }
+}
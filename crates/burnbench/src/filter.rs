@@ -0,0 +1,93 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Environment variable used by the runner to forward the `--filter` argument to the
+/// benchmark binaries.
+pub const FILTER_ENV_VAR: &str = "BURN_BENCH_FILTER";
+
+/// Select individual benchmark cases by matching a regular expression against their
+/// identifier.
+///
+/// The identifier of a case is its name followed by its shapes, for instance
+/// `matmul-general-float32 8x1024x1024`.
+#[derive(Debug, Clone)]
+pub struct BenchmarkFilter {
+    regex: Regex,
+}
+
+impl BenchmarkFilter {
+    /// Create a new filter from a regular expression.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            regex: Regex::new(pattern)?,
+        })
+    }
+
+    /// Return the filter defined by the `BURN_BENCH_FILTER` environment variable, if any.
+    ///
+    /// The filter is parsed once per process.
+    pub fn from_env() -> Option<&'static Self> {
+        static FILTER: OnceLock<Option<BenchmarkFilter>> = OnceLock::new();
+        FILTER
+            .get_or_init(|| {
+                let pattern = std::env::var(FILTER_ENV_VAR).ok()?;
+                match Self::new(&pattern) {
+                    Ok(filter) => Some(filter),
+                    Err(err) => panic!("Invalid benchmark filter '{pattern}': {err}"),
+                }
+            })
+            .as_ref()
+    }
+
+    /// Returns true if the benchmark case should be executed.
+    pub fn matches(&self, name: &str, shapes: &[Vec<usize>]) -> bool {
+        self.regex.is_match(&case_id(name, shapes))
+    }
+}
+
+/// Identifier of a benchmark case used for filtering.
+pub fn case_id(name: &str, shapes: &[Vec<usize>]) -> String {
+    let shapes = shapes
+        .iter()
+        .map(|shape| {
+            shape
+                .iter()
+                .map(|dim| dim.to_string())
+                .collect::<Vec<_>>()
+                .join("x")
+        })
+        .collect::<Vec<_>>();
+
+    if shapes.is_empty() {
+        name.to_string()
+    } else {
+        format!("{name} {}", shapes.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_id_includes_all_shapes() {
+        let id = case_id(
+            "matmul-general-float32",
+            &[vec![8, 1024, 1024], vec![8, 1024, 1]],
+        );
+        assert_eq!(id, "matmul-general-float32 8x1024x1024 8x1024x1");
+    }
+
+    #[test]
+    fn case_id_without_shapes_is_the_name() {
+        assert_eq!(case_id("autodiff", &[]), "autodiff");
+    }
+
+    #[test]
+    fn filter_matches_name_and_shape() {
+        let filter = BenchmarkFilter::new("^matmul-general.* 8x1024x").unwrap();
+        assert!(filter.matches("matmul-general-float32", &[vec![8, 1024, 1024]]));
+        assert!(!filter.matches("matmul-general-float32", &[vec![4, 2048, 2048]]));
+        assert!(!filter.matches("matmul-outer-float32", &[vec![8, 1024, 1024]]));
+    }
+}
@@ -1,30 +1,36 @@
 pub mod __private;
+mod backend;
 mod benchmark;
+mod case;
+mod device;
+mod endpoints;
+mod environment;
+mod execution;
+mod filter;
+mod memory;
 mod persistence;
+mod provenance;
 mod runner;
+mod storage;
 
 pub(crate) mod system_info;
 
+pub use backend::*;
 pub use benchmark::*;
+pub use case::*;
+pub use device::*;
+pub use endpoints::*;
+pub use environment::*;
+pub use execution::*;
+pub use filter::*;
+pub use memory::*;
 pub use persistence::*;
+pub use provenance::*;
 pub use runner::*;
+pub use storage::*;
 pub use system_info::*;
 
 const BENCHMARKS_TARGET_DIR: &str = "target/benchmarks";
-const TRACEL_CI_SERVER_BASE_URL: &str = if cfg!(debug_assertions) {
-    // development
-    "http://localhost:8000/v1/"
-} else {
-    // production
-    "https://user-benchmark-server-812794505978.northamerica-northeast1.run.app/v1/"
-};
-
-const BENCHMARK_WEBSITE_URL: &str = if cfg!(debug_assertions) || cfg!(test) {
-    "http://localhost:4321/"
-} else {
-    "https://burn.dev/"
-};
-
 #[macro_export]
 macro_rules! ci_errorln {
     ($($arg:tt)*) => {{
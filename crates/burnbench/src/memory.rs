@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Memory used by the process while the samples of a benchmark are executed.
+///
+/// The values that cannot be measured are `None`: the peak resident memory is only available on
+/// Linux and the allocations are only counted when the [CountingAllocator] is installed.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BenchmarkMemory {
+    /// Peak resident set size of the process, in bytes.
+    pub peak_resident_bytes: Option<u64>,
+    /// Mean number of heap allocations per sample.
+    pub allocations_per_sample: Option<u64>,
+    /// Mean number of bytes allocated on the heap per sample.
+    pub allocated_bytes_per_sample: Option<u64>,
+}
+
+/// A global allocator counting the heap allocations of the process.
+///
+/// Only the memory allocated on the host is counted, which makes it mostly relevant for CPU
+/// backends. It is installed with the [counting_allocator](crate::counting_allocator) macro.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        // Only written by the first allocation, to keep the cache line shared afterwards.
+        if !INSTALLED.load(Ordering::Relaxed) {
+            INSTALLED.store(true, Ordering::Relaxed);
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// Install the [CountingAllocator] as the global allocator of the benchmark binary.
+///
+/// ```rust,ignore
+/// burnbench::counting_allocator!();
+///
+/// fn main() {
+///     burnbench::bench_on_backend!();
+/// }
+/// ```
+#[macro_export]
+macro_rules! counting_allocator {
+    () => {
+        #[global_allocator]
+        static __BURNBENCH_ALLOCATOR: $crate::CountingAllocator = $crate::CountingAllocator;
+    };
+}
+
+/// Measure the memory used by the process while the samples are executed, excluding the
+/// preparation of their inputs.
+#[derive(Default)]
+pub(crate) struct MemoryProbe {
+    peak_resident_bytes: Option<u64>,
+    allocations: u64,
+    allocated_bytes: u64,
+}
+
+impl MemoryProbe {
+    /// Measure the memory used while executing the function: the peak resident memory of the
+    /// process is reset before it and read after it, and its allocations are counted.
+    pub(crate) fn measure<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        // Writing 5 resets the peak resident set size reported by the kernel.
+        fs::write("/proc/self/clear_refs", "5").ok();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+
+        let output = f();
+
+        self.allocations += ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+        self.allocated_bytes += ALLOCATED_BYTES.load(Ordering::Relaxed) - allocated_bytes;
+        let peak = fs::read_to_string("/proc/self/status")
+            .ok()
+            .and_then(|status| parse_peak_resident_bytes(&status));
+        self.peak_resident_bytes = self.peak_resident_bytes.max(peak);
+        output
+    }
+
+    /// Stop the measurement of the given number of samples.
+    pub(crate) fn finish(self, num_samples: usize) -> BenchmarkMemory {
+        let per_sample = |total: u64| total / num_samples.max(1) as u64;
+        let counted = INSTALLED.load(Ordering::Relaxed);
+
+        BenchmarkMemory {
+            peak_resident_bytes: self.peak_resident_bytes,
+            allocations_per_sample: counted.then(|| per_sample(self.allocations)),
+            allocated_bytes_per_sample: counted.then(|| per_sample(self.allocated_bytes)),
+        }
+    }
+}
+
+/// Parse the peak resident set size from the content of `/proc/self/status`.
+fn parse_peak_resident_bytes(status: &str) -> Option<u64> {
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .split_whitespace()
+        .next()?
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_proc_status() {
+        let status =
+            "Name:\tbench\nVmPeak:\t  300000 kB\nVmHWM:\t    2048 kB\nVmRSS:\t    1024 kB\n";
+        assert_eq!(parse_peak_resident_bytes(status), Some(2048 * 1024));
+        assert_eq!(parse_peak_resident_bytes("Name:\tbench\n"), None);
+    }
+
+    #[test]
+    fn allocations_are_not_reported_without_the_allocator() {
+        let mut probe = MemoryProbe::default();
+        let buffer = probe.measure(|| vec![1u8; 1 << 20]);
+        let memory = probe.finish(10);
+
+        assert_eq!(memory.allocations_per_sample, None);
+        assert_eq!(memory.allocated_bytes_per_sample, None);
+        if cfg!(target_os = "linux") {
+            assert!(memory.peak_resident_bytes.unwrap() >= buffer.len() as u64);
+        }
+    }
+}
@@ -1,10 +1,10 @@
-use crate::auth::get_auth_header_value;
+use crate::storage::{Run, RunMetadata};
 use crate::system_info::BenchmarkSystemInfo;
+use crate::{BenchmarkMemory, EnvironmentChecks, ExecutionSettings};
+use crate::{JsonFilesSink, ResultSink, SINKS_ENV_VAR, SinkConfig, upload_records};
 
-use reqwest::header::{ACCEPT, AUTHORIZATION, HeaderMap, USER_AGENT};
-use serde::{Deserialize, Serialize, Serializer, de::Visitor, ser::SerializeStruct};
+use serde::{Deserialize, Serialize, Serializer, de::Visitor, ser::SerializeMap};
 use std::time::Duration;
-use std::{fs, io::Write};
 
 /// Result of a benchmark run, with metadata
 #[derive(Default, Clone, Serialize, Deserialize)]
@@ -13,8 +13,17 @@ pub struct BenchmarkResult {
     pub raw: BenchmarkDurations,
     /// Computed values for the run
     pub computed: BenchmarkComputations,
+    /// Memory used during the run
+    #[serde(default)]
+    pub memory: BenchmarkMemory,
+    /// Custom metrics reported by the benchmark
+    #[serde(default)]
+    pub metrics: Vec<BenchmarkMetric>,
     /// Git commit hash of the commit in which the run occurred
     pub git_hash: String,
+    /// Whether the working tree had uncommitted changes, `None` if unknown
+    #[serde(default)]
+    pub git_dirty: Option<bool>,
     /// Name of the benchmark
     pub name: String,
     /// Options passed to the benchmark
@@ -25,6 +34,25 @@ pub struct BenchmarkResult {
     pub timestamp: u128,
 }
 
+/// A custom named value reported by a benchmark.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkMetric {
+    /// Name of the metric, e.g. `tokens/sec`.
+    pub name: String,
+    /// Value of the metric.
+    pub value: f64,
+}
+
+impl BenchmarkMetric {
+    /// Create a new metric.
+    pub fn new(name: impl Into<String>, value: f64) -> Self {
+        Self {
+            name: name.into(),
+            value,
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct BenchmarkComputations {
     /// Mean of all the durations.
@@ -60,6 +88,10 @@ pub struct BenchmarkDurations {
     pub timing_method: TimingMethod,
     /// All durations of the run, in the order they were benchmarked
     pub durations: Vec<Duration>,
+    /// Durations reported by the device for each sample when measured with dual timing, empty
+    /// otherwise.
+    #[serde(default)]
+    pub device_durations: Vec<Duration>,
 }
 
 impl BenchmarkDurations {
@@ -73,8 +105,22 @@ impl BenchmarkDurations {
         (min, max, median)
     }
 
+    /// Ratio between the median system and device durations, measuring the launch and
+    /// synchronization overhead. `None` without device durations.
+    pub fn device_overhead(&self) -> Option<f64> {
+        let median = |durations: &[Duration]| {
+            let mut sorted = durations.to_vec();
+            sorted.sort();
+            sorted.get(sorted.len() / 2).copied()
+        };
+        let system = median(&self.durations)?;
+        let device = median(&self.device_durations)?;
+
+        (!device.is_zero()).then(|| system.as_secs_f64() / device.as_secs_f64())
+    }
+
     /// Returns the median duration among all durations
-    pub(crate) fn mean_duration(&self) -> Duration {
+    pub fn mean_duration(&self) -> Duration {
         self.durations.iter().sum::<Duration>() / self.durations.len() as u32
     }
 
@@ -91,7 +137,15 @@ impl BenchmarkDurations {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+/// Environment variable used by the runner to forward the `--timing-method` argument to the
+/// benchmark binaries.
+pub const TIMING_METHOD_ENV_VAR: &str = "BURN_BENCH_TIMING_METHOD";
+
+/// Environment variable used by the runner to forward the `--dual-timing` argument to the
+/// benchmark binaries.
+pub const DUAL_TIMING_ENV_VAR: &str = "BURN_BENCH_DUAL_TIMING";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TimingMethod {
     /// Time measurements come from full timing of execution + sync
     /// calls.
@@ -102,17 +156,66 @@ pub enum TimingMethod {
     Device,
 }
 
+impl TimingMethod {
+    /// Return the timing method defined by the `BURN_BENCH_TIMING_METHOD` environment variable,
+    /// or the system timing if unset.
+    pub fn from_env() -> Self {
+        match std::env::var(TIMING_METHOD_ENV_VAR) {
+            Ok(value) => value
+                .parse()
+                .unwrap_or_else(|err| panic!("Invalid timing method '{value}': {err}")),
+            Err(_) => Self::System,
+        }
+    }
+}
+
+impl std::fmt::Display for TimingMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimingMethod::System => f.write_str("system"),
+            TimingMethod::Device => f.write_str("device"),
+        }
+    }
+}
+
+impl std::str::FromStr for TimingMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(TimingMethod::System),
+            "device" => Ok(TimingMethod::Device),
+            _ => Err(format!(
+                "unknown timing method '{s}', expected one of: system, device"
+            )),
+        }
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct BenchmarkRecord {
     pub backend: String,
     pub device: String,
+    /// Selector of the device, like `discrete:1` or `cpu`, `None` for the records saved before it
+    /// was recorded.
+    pub device_selector: Option<String>,
     pub feature: String,
     pub burn_version: String,
+    /// Commit of burn resolved by the build when it is a git dependency.
+    pub burn_commit: Option<String>,
     pub system_info: BenchmarkSystemInfo,
+    pub environment: EnvironmentChecks,
+    pub execution: ExecutionSettings,
     pub results: BenchmarkResult,
+    /// Run in which the benchmark was executed.
+    pub run: RunMetadata,
+    /// Version of the format of the record, see [SCHEMA_VERSION](crate::SCHEMA_VERSION).
+    pub schema_version: u32,
+    /// Keys unknown to this version of burnbench, preserved when the record is saved again.
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-/// Save the benchmarks results on disk.
+/// Save the benchmarks results on disk and write them to the configured [sinks](crate::ResultSink).
 ///
 /// The structure is flat so that it can be easily queried from a database
 /// like MongoDB.
@@ -121,21 +224,37 @@ pub struct BenchmarkRecord {
 ///  [
 ///    {
 ///      "backend": "backend name",
+///      "burnCommit": "commit of burn resolved from the Cargo.lock file, null if not a git dependency",
 ///      "device": "device name",
+///      "deviceSelector": "selector of the device, like discrete:1 or cpu",
+///      "environmentChecks": [{"name": "check name", "status": "passed", "value": "value", "message": null}, ...],
+///      "executionSettings": {"cpuSet": "0-3", "threads": 4, "nice": null},
 ///      "feature": "feature name",
+///      "gitDirty": "true if the working tree had uncommitted changes, null if unknown",
 ///      "gitHash": "hash",
 ///      "max": "duration in microseconds",
+///      "maxNs": "duration in nanoseconds",
 ///      "mean": "duration in microseconds",
+///      "meanNs": "duration in nanoseconds",
+///      "memory": {"peakResidentBytes": "bytes", "allocationsPerSample": "count", "allocatedBytesPerSample": "bytes"},
+///      "metrics": [{"name": "metric name", "value": "metric value"}, ...],
 ///      "median": "duration in microseconds",
+///      "medianNs": "duration in nanoseconds",
 ///      "min": "duration in microseconds",
+///      "minNs": "duration in nanoseconds",
 ///      "name": "benchmark name",
 ///      "numSamples": "number of samples",
 ///      "operation": "operation name",
+///      "rawDeviceDurations": [{"secs": "number of seconds", "nanos": "number of nanons"}, ...],
 ///      "rawDurations": [{"secs": "number of seconds", "nanos": "number of nanons"}, ...],
+///      "run": {"id": "run uuid", "startedAt": "timestamp", "tags": {"key": "value"}, "note": "note"},
+///      "schemaVersion": "version of the format of the record",
 ///      "shapes": [[shape 1], [shape 2], ...],
 ///      "systemInfo": { "cpus": ["cpu1", "cpu2", ...], "gpus": ["gpu1", "gpu2", ...]}
 ///      "timestamp": "timestamp",
+///      "timingMethod": "System or Device",
 ///      "variance": "duration in microseconds",
+///      "varianceNs": "duration in nanoseconds",
 ///    },
 ///    { ... }
 /// ]
@@ -145,83 +264,57 @@ pub fn save_records(
     url: Option<&str>,
     token: Option<&str>,
 ) -> Result<(), std::io::Error> {
-    let cache_dir = dirs::home_dir()
-        .expect("Home directory should exist")
-        .join(".cache")
-        .join("burn")
-        .join("burnbench");
-
-    if !cache_dir.exists() {
-        fs::create_dir_all(&cache_dir)?;
+    let run = Run::current()?;
+    let records = records
+        .into_iter()
+        .map(|mut record| {
+            record.run = run.metadata.clone();
+            record
+        })
+        .collect::<Vec<_>>();
+
+    JsonFilesSink::new(run)
+        .write(&records)
+        .map_err(std::io::Error::other)?;
+
+    let sinks = SinkConfig::from_env()
+        .map_err(|err| std::io::Error::other(format!("invalid {SINKS_ENV_VAR} ({err})")))?;
+    for sink in sinks.iter().map(SinkConfig::build) {
+        println!("Sharing results to {}...", sink.name());
+        match sink.write(&records) {
+            Ok(()) => println!("Results shared successfully."),
+            Err(err) => println!("Failed to share results to {}: {err}", sink.name()),
+        }
     }
 
-    for record in records {
-        let file_name = format!(
-            "bench_{}_{}.json",
-            record.results.name, record.results.timestamp
-        );
-        let file_path = cache_dir.join(file_name);
-        let file =
-            fs::File::create(file_path.clone()).expect("Benchmark file should exist or be created");
-        serde_json::to_writer_pretty(file, &record)
-            .expect("Benchmark file should be updated with benchmark results");
-
-        // Append the benchmark result filepath in the benchmark_results.tx file of
-        // cache folder to be later picked by benchrun
-        let benchmark_results_path = cache_dir.join("benchmark_results.txt");
-        let mut benchmark_results_file = fs::OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(benchmark_results_path)
-            .unwrap();
-        benchmark_results_file
-            .write_all(format!("{}\n", file_path.to_string_lossy()).as_bytes())
-            .unwrap();
-
-        if let Some(upload_url) = url {
-            upload_record(
-                &record,
-                token.expect("An auth token should be provided."),
-                upload_url,
-            );
+    if let Some(upload_url) = url {
+        println!("Sharing results...");
+        let token = token.expect("An auth token should be provided.");
+        // The token given to the benches cannot be refreshed by them.
+        match upload_records(upload_url, token, false, &records) {
+            Ok(()) => println!("Results shared successfully."),
+            Err(err) => println!("Failed to share results ({err})."),
         }
     }
 
     Ok(())
 }
 
-fn upload_record(record: &BenchmarkRecord, token: &str, url: &str) {
-    println!("Sharing results...");
-    let client = reqwest::blocking::Client::new();
-    let mut headers = HeaderMap::new();
-    headers.insert(USER_AGENT, "burnbench".parse().unwrap());
-    headers.insert(ACCEPT, "application/json".parse().unwrap());
-    headers.insert(AUTHORIZATION, get_auth_header_value(token).parse().unwrap());
-    // post the benchmark record
-    let response = client
-        .post(url)
-        .headers(headers)
-        .json(record)
-        .send()
-        .expect("Request should be sent successfully.");
-    if response.status().is_success() {
-        println!("Results shared successfully.");
-    } else {
-        println!("Failed to share results. Status: {}", response.status());
-    }
-}
-
 /// Macro to easily serialize each field in a flatten manner.
 /// This macro automatically computes the number of fields to serialize
 /// and allows specifying a custom serialization key for each field.
+/// The unknown keys of the record are serialized after its fields.
 macro_rules! serialize_fields {
     ($serializer:expr, $record:expr, $(($key:expr, $field:expr)),*) => {{
         // Hacky way to get the fields count
         let fields_count = [ $(stringify!($key),)+ ].len();
-        let mut state = $serializer.serialize_struct("BenchmarkRecord", fields_count)?;
+        let mut state = $serializer.serialize_map(Some(fields_count + $record.extra.len()))?;
         $(
-            state.serialize_field($key, $field)?;
+            state.serialize_entry($key, $field)?;
         )*
+        for (key, value) in &$record.extra {
+            state.serialize_entry(key, value)?;
+        }
             state.end()
     }};
 }
@@ -236,21 +329,37 @@ impl Serialize for BenchmarkRecord {
             self,
             ("backend", &self.backend),
             ("device", &self.device),
+            ("deviceSelector", &self.device_selector),
+            ("environmentChecks", &self.environment),
+            ("executionSettings", &self.execution),
             ("feature", &self.feature),
+            ("gitDirty", &self.results.git_dirty),
             ("gitHash", &self.results.git_hash),
             ("burnVersion", &self.burn_version),
+            ("burnCommit", &self.burn_commit),
             ("max", &self.results.computed.max.as_micros()),
+            ("maxNs", &self.results.computed.max.as_nanos()),
             ("mean", &self.results.computed.mean.as_micros()),
+            ("meanNs", &self.results.computed.mean.as_nanos()),
+            ("memory", &self.results.memory),
+            ("metrics", &self.results.metrics),
             ("median", &self.results.computed.median.as_micros()),
+            ("medianNs", &self.results.computed.median.as_nanos()),
             ("min", &self.results.computed.min.as_micros()),
+            ("minNs", &self.results.computed.min.as_nanos()),
             ("name", &self.results.name),
             ("numSamples", &self.results.raw.durations.len()),
             ("options", &self.results.options),
+            ("rawDeviceDurations", &self.results.raw.device_durations),
             ("rawDurations", &self.results.raw.durations),
+            ("run", &self.run),
+            ("schemaVersion", &self.schema_version),
             ("systemInfo", &self.system_info),
             ("shapes", &self.results.shapes),
             ("timestamp", &self.results.timestamp),
-            ("variance", &self.results.computed.variance.as_micros())
+            ("timingMethod", &self.results.raw.timing_method),
+            ("variance", &self.results.computed.variance.as_micros()),
+            ("varianceNs", &self.results.computed.variance.as_nanos())
         )
     }
 }
@@ -267,41 +376,80 @@ impl<'de> Visitor<'de> for BenchmarkRecordVisitor {
         A: serde::de::MapAccess<'de>,
     {
         let mut br = BenchmarkRecord::default();
+        // The nanosecond keys take precedence over the microsecond keys, whatever their order.
+        let mut precise = PreciseComputations::default();
         while let Some(key) = map.next_key::<String>()? {
             match key.as_str() {
                 "backend" => br.backend = map.next_value::<String>()?,
                 "device" => br.device = map.next_value::<String>()?,
+                "deviceSelector" => br.device_selector = map.next_value::<Option<String>>()?,
+                "environmentChecks" => br.environment = map.next_value::<EnvironmentChecks>()?,
+                "executionSettings" => br.execution = map.next_value::<ExecutionSettings>()?,
                 "feature" => br.feature = map.next_value::<String>()?,
                 "burnVersion" => br.burn_version = map.next_value::<String>()?,
+                "burnCommit" => br.burn_commit = map.next_value::<Option<String>>()?,
+                "gitDirty" => br.results.git_dirty = map.next_value::<Option<bool>>()?,
                 "gitHash" => br.results.git_hash = map.next_value::<String>()?,
                 "name" => br.results.name = map.next_value::<String>()?,
                 "max" => {
                     let value = map.next_value::<u64>()?;
                     br.results.computed.max = Duration::from_micros(value);
                 }
+                "maxNs" => precise.max = Some(map.next_value::<u64>()?),
                 "mean" => {
                     let value = map.next_value::<u64>()?;
                     br.results.computed.mean = Duration::from_micros(value);
                 }
+                "meanNs" => precise.mean = Some(map.next_value::<u64>()?),
+                "memory" => br.results.memory = map.next_value::<BenchmarkMemory>()?,
+                "metrics" => br.results.metrics = map.next_value::<Vec<BenchmarkMetric>>()?,
                 "median" => {
                     let value = map.next_value::<u64>()?;
                     br.results.computed.median = Duration::from_micros(value);
                 }
+                "medianNs" => precise.median = Some(map.next_value::<u64>()?),
                 "min" => {
                     let value = map.next_value::<u64>()?;
                     br.results.computed.min = Duration::from_micros(value);
                 }
+                "minNs" => precise.min = Some(map.next_value::<u64>()?),
                 "numSamples" => _ = map.next_value::<usize>()?,
                 "options" => br.results.options = map.next_value::<Option<String>>()?,
+                "rawDeviceDurations" => {
+                    br.results.raw.device_durations = map.next_value::<Vec<Duration>>()?
+                }
                 "rawDurations" => br.results.raw.durations = map.next_value::<Vec<Duration>>()?,
+                "run" => br.run = map.next_value::<RunMetadata>()?,
+                "schemaVersion" => br.schema_version = map.next_value::<u32>()?,
                 "shapes" => br.results.shapes = map.next_value::<Vec<Vec<usize>>>()?,
                 "systemInfo" => br.system_info = map.next_value::<BenchmarkSystemInfo>()?,
                 "timestamp" => br.results.timestamp = map.next_value::<u128>()?,
+                "timingMethod" => {
+                    br.results.raw.timing_method = map.next_value::<TimingMethod>()?
+                }
                 "variance" => {
                     let value = map.next_value::<u64>()?;
                     br.results.computed.variance = Duration::from_micros(value)
                 }
-                _ => panic!("Unexpected Key: {}", key),
+                "varianceNs" => precise.variance = Some(map.next_value::<u64>()?),
+                // Keys added by newer versions of burnbench
+                _ => {
+                    let value = map.next_value::<serde_json::Value>()?;
+                    br.extra.insert(key, value);
+                }
+            }
+        }
+
+        let computed = &mut br.results.computed;
+        for (nanos, duration) in [
+            (precise.max, &mut computed.max),
+            (precise.mean, &mut computed.mean),
+            (precise.median, &mut computed.median),
+            (precise.min, &mut computed.min),
+            (precise.variance, &mut computed.variance),
+        ] {
+            if let Some(nanos) = nanos {
+                *duration = Duration::from_nanos(nanos);
             }
         }
 
@@ -309,6 +457,16 @@ impl<'de> Visitor<'de> for BenchmarkRecordVisitor {
     }
 }
 
+/// Computed values read from the nanosecond keys of a record.
+#[derive(Default)]
+struct PreciseComputations {
+    max: Option<u64>,
+    mean: Option<u64>,
+    median: Option<u64>,
+    min: Option<u64>,
+    variance: Option<u64>,
+}
+
 impl<'de> Deserialize<'de> for BenchmarkRecord {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -420,6 +578,7 @@ mod tests {
     fn test_min_max_median_durations_even_number_of_samples() {
         let durations = BenchmarkDurations {
             timing_method: TimingMethod::System,
+            device_durations: vec![],
             durations: vec![
                 Duration::new(10, 0),
                 Duration::new(20, 0),
@@ -438,6 +597,7 @@ mod tests {
     fn test_min_max_median_durations_odd_number_of_samples() {
         let durations = BenchmarkDurations {
             timing_method: TimingMethod::System,
+            device_durations: vec![],
             durations: vec![
                 Duration::new(18, 5),
                 Duration::new(20, 0),
@@ -455,6 +615,7 @@ mod tests {
     fn test_mean_duration() {
         let durations = BenchmarkDurations {
             timing_method: TimingMethod::System,
+            device_durations: vec![],
             durations: vec![
                 Duration::new(10, 0),
                 Duration::new(20, 0),
@@ -470,6 +631,7 @@ mod tests {
     fn test_variance_duration() {
         let durations = BenchmarkDurations {
             timing_method: TimingMethod::System,
+            device_durations: vec![],
             durations: vec![
                 Duration::new(10, 0),
                 Duration::new(20, 0),
@@ -482,4 +644,75 @@ mod tests {
         let variance = durations.variance_duration(mean);
         assert_eq!(variance, Duration::from_secs(200));
     }
+
+    #[test]
+    fn timing_method_roundtrip() {
+        for method in [TimingMethod::System, TimingMethod::Device] {
+            assert_eq!(method.to_string().parse(), Ok(method));
+        }
+        assert!("wall".parse::<TimingMethod>().is_err());
+    }
+
+    #[test]
+    fn device_overhead_is_the_ratio_of_the_medians() {
+        let durations = BenchmarkDurations {
+            timing_method: TimingMethod::System,
+            durations: vec![Duration::from_micros(30), Duration::from_micros(40)],
+            device_durations: vec![Duration::from_micros(10), Duration::from_micros(20)],
+        };
+        assert_eq!(durations.device_overhead(), Some(2.0));
+
+        let durations = BenchmarkDurations {
+            device_durations: vec![],
+            ..durations
+        };
+        assert_eq!(durations.device_overhead(), None);
+    }
+
+    #[test]
+    fn unknown_keys_are_preserved() {
+        let record = serde_json::from_str::<BenchmarkRecord>(
+            r#"{"name": "unary", "schemaVersion": 7, "newKey": {"nested": [1, 2]}}"#,
+        )
+        .unwrap();
+        assert_eq!(record.results.name, "unary");
+        assert_eq!(record.schema_version, 7);
+        assert_eq!(record.extra["newKey"]["nested"][1], 2);
+
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["newKey"]["nested"][0], 1);
+        assert_eq!(json["schemaVersion"], 7);
+    }
+
+    #[test]
+    fn nanosecond_keys_take_precedence() {
+        let record = serde_json::from_str::<BenchmarkRecord>(
+            r#"{"medianNs": 850, "median": 1, "min": 0, "minNs": 420, "max": 2}"#,
+        )
+        .unwrap();
+        assert_eq!(record.results.computed.median, Duration::from_nanos(850));
+        assert_eq!(record.results.computed.min, Duration::from_nanos(420));
+        assert_eq!(record.results.computed.max, Duration::from_micros(2));
+
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["median"], 0);
+        assert_eq!(json["medianNs"], 850);
+    }
+
+    #[test]
+    fn run_metadata_is_saved_in_records() {
+        let record = BenchmarkRecord {
+            run: RunMetadata {
+                id: "67e55044-10b1-426f-9247-bb680e5fe0c8".to_string(),
+                started_at: 1710208069697,
+                tags: [("gpu".to_string(), "a100".to_string())].into(),
+                note: Some("baseline".to_string()),
+            },
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&record).unwrap();
+        let parsed = serde_json::from_str::<BenchmarkRecord>(&json).unwrap();
+        assert_eq!(parsed.run, record.run);
+    }
 }
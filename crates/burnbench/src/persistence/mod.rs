@@ -1,2 +1,10 @@
 mod base;
+mod schema;
+mod sink;
+#[cfg(test)]
+mod test_server;
+mod upload;
 pub use base::*;
+pub use schema::*;
+pub use sink::*;
+pub(crate) use upload::*;
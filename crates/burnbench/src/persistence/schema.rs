@@ -0,0 +1,153 @@
+use crate::{BenchmarkComputations, BenchmarkDurations};
+
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Version of the format of the benchmark records written by this version of burnbench.
+///
+/// It is stored in the `schemaVersion` key of every record, the records written before the key
+/// existed are version 0. Bump it and add a migration to [MIGRATIONS] when the format changes.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Functions upgrading a record from the version of their index to the next one.
+const MIGRATIONS: [fn(&mut Map<String, Value>); SCHEMA_VERSION as usize] =
+    [migrate_v0_to_v1, migrate_v1_to_v2];
+
+/// Return the schema version of a serialized record.
+pub fn schema_version(record: &Value) -> u32 {
+    record
+        .get("schemaVersion")
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32
+}
+
+/// Upgrade a serialized record to the current [SCHEMA_VERSION].
+///
+/// Records written by a newer version of burnbench are returned unchanged, their unknown keys are
+/// preserved when deserialized.
+pub fn migrate_record(mut record: Value) -> Result<Value, String> {
+    let version = schema_version(&record);
+    let Some(fields) = record.as_object_mut() else {
+        return Err("a benchmark record should be a JSON object".to_string());
+    };
+
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        migration(fields);
+    }
+    if version < SCHEMA_VERSION {
+        fields.insert("schemaVersion".to_string(), SCHEMA_VERSION.into());
+    }
+
+    Ok(record)
+}
+
+/// Upgrade a record file to the current [SCHEMA_VERSION] in place.
+///
+/// Returns true if the file was rewritten.
+pub fn migrate_record_file<P: AsRef<Path>>(path: P) -> Result<bool, String> {
+    let path = path.as_ref();
+    let content =
+        fs::read_to_string(path).map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+    let record: Value = serde_json::from_str(&content)
+        .map_err(|err| format!("invalid record {}: {err}", path.display()))?;
+    if schema_version(&record) >= SCHEMA_VERSION {
+        return Ok(false);
+    }
+
+    let record = migrate_record(record)?;
+    let content = serde_json::to_string_pretty(&record).unwrap();
+    fs::write(path, content).map_err(|err| format!("cannot write {}: {err}", path.display()))?;
+
+    Ok(true)
+}
+
+/// Records written before the timing method was stored were measured with the system clock, and
+/// some of them do not have the number of samples.
+fn migrate_v0_to_v1(fields: &mut Map<String, Value>) {
+    fields
+        .entry("timingMethod")
+        .or_insert_with(|| "System".into());
+
+    if !fields.contains_key("numSamples") {
+        let num_samples = fields
+            .get("rawDurations")
+            .and_then(Value::as_array)
+            .map(|durations| durations.len())
+            .unwrap_or(0);
+        fields.insert("numSamples".to_string(), num_samples.into());
+    }
+}
+
+/// The computed values are stored with a nanosecond precision. They are computed again from the
+/// raw durations when available, the microsecond values are used otherwise.
+fn migrate_v1_to_v2(fields: &mut Map<String, Value>) {
+    let durations = fields
+        .get("rawDurations")
+        .and_then(|durations| serde_json::from_value::<Vec<Duration>>(durations.clone()).ok())
+        .filter(|durations| !durations.is_empty());
+
+    if let Some(durations) = durations {
+        let computed = BenchmarkComputations::new(&BenchmarkDurations {
+            durations,
+            ..Default::default()
+        });
+        for (key, duration) in [
+            ("maxNs", computed.max),
+            ("meanNs", computed.mean),
+            ("medianNs", computed.median),
+            ("minNs", computed.min),
+            ("varianceNs", computed.variance),
+        ] {
+            fields.insert(key.to_string(), (duration.as_nanos() as u64).into());
+        }
+        return;
+    }
+
+    for key in ["max", "mean", "median", "min", "variance"] {
+        if let Some(micros) = fields.get(key).and_then(Value::as_u64) {
+            fields.insert(format!("{key}Ns"), (micros * 1000).into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn legacy_records_are_upgraded() {
+        let record = json!({
+            "name": "unary",
+            "rawDurations": [{"secs": 0, "nanos": 10}, {"secs": 0, "nanos": 20}],
+        });
+        assert_eq!(schema_version(&record), 0);
+
+        let record = migrate_record(record).unwrap();
+        assert_eq!(schema_version(&record), SCHEMA_VERSION);
+        assert_eq!(record["timingMethod"], "System");
+        assert_eq!(record["numSamples"], 2);
+        assert_eq!(record["medianNs"], 20);
+        assert_eq!(record["minNs"], 10);
+    }
+
+    #[test]
+    fn precise_values_fall_back_to_microseconds() {
+        let record = json!({"schemaVersion": 1, "median": 3, "variance": 0});
+
+        let record = migrate_record(record).unwrap();
+        assert_eq!(record["medianNs"], 3000);
+        assert_eq!(record["varianceNs"], 0);
+        assert!(record.get("maxNs").is_none());
+    }
+
+    #[test]
+    fn newer_records_are_not_modified() {
+        let record = json!({"schemaVersion": SCHEMA_VERSION + 1, "name": "unary"});
+
+        assert_eq!(migrate_record(record.clone()).unwrap(), record);
+        assert!(migrate_record(json!([1, 2])).is_err());
+    }
+}
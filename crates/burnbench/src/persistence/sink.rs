@@ -0,0 +1,449 @@
+use crate::BenchmarkRecord;
+use crate::storage::Run;
+
+use reqwest::header::{ACCEPT, CONTENT_TYPE, HeaderMap, HeaderName, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Environment variable holding the sinks of the benchmark records as a JSON array of
+/// [SinkConfig].
+pub const SINKS_ENV_VAR: &str = "BURN_BENCH_SINKS";
+
+/// A destination of the benchmark records.
+pub trait ResultSink {
+    /// Name of the sink displayed in the messages.
+    fn name(&self) -> String;
+
+    /// Write the records of a bench.
+    fn write(&self, records: &[BenchmarkRecord]) -> Result<(), String>;
+}
+
+/// Configuration of a sink, given with `--sink` or in the `BURN_BENCH_SINKS` environment variable.
+///
+/// The records are always saved as JSON files in the directory of the run, which the report reads.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SinkConfig {
+    /// Append the records to a JSON lines file.
+    JsonLines { path: PathBuf },
+    /// Post each record as JSON to an HTTP endpoint.
+    Http {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    /// Post the records in the InfluxDB line protocol to an HTTP endpoint, e.g. the
+    /// `/api/v2/write` endpoint of InfluxDB.
+    Influx {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+}
+
+impl SinkConfig {
+    /// Parse a sink given as `jsonl=<path>`, `http=<url>` or `influx=<url>`.
+    pub fn parse(sink: &str) -> Result<Self, String> {
+        let invalid =
+            || format!("invalid sink '{sink}', expected jsonl=<path>, http=<url> or influx=<url>");
+        let (kind, target) = sink.split_once('=').ok_or_else(invalid)?;
+
+        match kind {
+            "jsonl" => Ok(Self::JsonLines {
+                path: PathBuf::from(target),
+            }),
+            "http" => Ok(Self::Http {
+                url: target.to_string(),
+                headers: BTreeMap::new(),
+            }),
+            "influx" => Ok(Self::Influx {
+                url: target.to_string(),
+                headers: BTreeMap::new(),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Add headers to the requests of the HTTP sinks.
+    pub fn with_headers(mut self, extra: &[(String, String)]) -> Self {
+        if let Self::Http { headers, .. } | Self::Influx { headers, .. } = &mut self {
+            headers.extend(extra.iter().cloned());
+        }
+        self
+    }
+
+    /// Resolve the relative path of a JSON lines sink against the given directory, the benches
+    /// are executed from the directory of their crate.
+    pub fn relative_to(mut self, dir: &Path) -> Self {
+        if let Self::JsonLines { path } = &mut self {
+            *path = dir.join(&path);
+        }
+        self
+    }
+
+    /// The sinks configured with the `BURN_BENCH_SINKS` environment variable, none when unset.
+    pub fn from_env() -> Result<Vec<Self>, String> {
+        match std::env::var(SINKS_ENV_VAR) {
+            Ok(sinks) => serde_json::from_str(&sinks).map_err(|err| err.to_string()),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// Create the configured sink.
+    pub fn build(&self) -> Box<dyn ResultSink> {
+        match self {
+            Self::JsonLines { path } => Box::new(JsonLinesSink::new(path.clone())),
+            Self::Http { url, headers } => Box::new(HttpSink::new(url, headers.clone())),
+            Self::Influx { url, headers } => Box::new(InfluxSink::new(url, headers.clone())),
+        }
+    }
+}
+
+/// Parse an HTTP header given as `Name: value`.
+pub fn parse_header(header: &str) -> Result<(String, String), String> {
+    match header.split_once(':') {
+        Some((name, value)) if HeaderName::from_bytes(name.trim().as_bytes()).is_ok() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("invalid header '{header}', expected 'Name: value'")),
+    }
+}
+
+/// Save each record as a JSON file in the directory of the run.
+pub(crate) struct JsonFilesSink {
+    run: Run,
+}
+
+impl JsonFilesSink {
+    pub(crate) fn new(run: Run) -> Self {
+        Self { run }
+    }
+}
+
+impl ResultSink for JsonFilesSink {
+    fn name(&self) -> String {
+        self.run.path.display().to_string()
+    }
+
+    fn write(&self, records: &[BenchmarkRecord]) -> Result<(), String> {
+        for record in records {
+            let path = self
+                .run
+                .record_path(&record.results.name, record.results.timestamp);
+            fs::write(&path, serde_json::to_string_pretty(record).unwrap())
+                .map_err(|err| format!("cannot write {}: {err}", path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Append the records to a JSON lines file, one record per line.
+pub struct JsonLinesSink {
+    path: PathBuf,
+}
+
+impl JsonLinesSink {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl ResultSink for JsonLinesSink {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn write(&self, records: &[BenchmarkRecord]) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).ok();
+        }
+        let mut lines = String::new();
+        for record in records {
+            lines.push_str(&serde_json::to_string(record).unwrap());
+            lines.push('\n');
+        }
+
+        fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .map_err(|err| format!("cannot write {}: {err}", self.path.display()))
+    }
+}
+
+/// Post each record as JSON to an HTTP endpoint.
+pub struct HttpSink {
+    url: String,
+    headers: BTreeMap<String, String>,
+}
+
+impl HttpSink {
+    pub fn new(url: &str, headers: BTreeMap<String, String>) -> Self {
+        Self {
+            url: url.to_string(),
+            headers,
+        }
+    }
+}
+
+impl ResultSink for HttpSink {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn write(&self, records: &[BenchmarkRecord]) -> Result<(), String> {
+        let client = reqwest::blocking::Client::new();
+        for record in records {
+            let body = serde_json::to_string(record).unwrap();
+            post(&client, &self.url, &self.headers, "application/json", body)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Post the records in the InfluxDB line protocol to an HTTP endpoint.
+///
+/// Each record is written as a point of the `burnbench` measurement, tagged with the benchmark,
+/// backend, device, feature, burn version and run, with the computed durations in nanoseconds.
+pub struct InfluxSink {
+    url: String,
+    headers: BTreeMap<String, String>,
+}
+
+impl InfluxSink {
+    pub fn new(url: &str, headers: BTreeMap<String, String>) -> Self {
+        Self {
+            url: url.to_string(),
+            headers,
+        }
+    }
+}
+
+impl ResultSink for InfluxSink {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn write(&self, records: &[BenchmarkRecord]) -> Result<(), String> {
+        let body = records
+            .iter()
+            .map(influx_line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let client = reqwest::blocking::Client::new();
+
+        post(&client, &self.url, &self.headers, "text/plain", body)
+    }
+}
+
+fn post(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    content_type: &str,
+    body: String,
+) -> Result<(), String> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(USER_AGENT, "burnbench".parse().unwrap());
+    header_map.insert(ACCEPT, "application/json".parse().unwrap());
+    header_map.insert(CONTENT_TYPE, content_type.parse().unwrap());
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|err| err.to_string())?;
+        header_map.insert(name, value.parse().map_err(|_| "invalid header value")?);
+    }
+
+    let response = client
+        .post(url)
+        .headers(header_map)
+        .body(body)
+        .send()
+        .map_err(|err| err.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("status {}", response.status()))
+    }
+}
+
+/// Format a record as a line of the InfluxDB line protocol.
+fn influx_line(record: &BenchmarkRecord) -> String {
+    let escape = |value: &str| {
+        value
+            .replace('\\', "\\\\")
+            .replace(',', "\\,")
+            .replace('=', "\\=")
+            .replace(' ', "\\ ")
+    };
+    let tags = [
+        ("name", record.results.name.as_str()),
+        ("backend", record.backend.as_str()),
+        ("device", record.device.as_str()),
+        ("feature", record.feature.as_str()),
+        ("burnVersion", record.burn_version.as_str()),
+        ("runId", record.run.id.as_str()),
+    ]
+    .iter()
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(key, value)| format!(",{key}={}", escape(value)))
+    .collect::<String>();
+
+    let computed = &record.results.computed;
+    let shapes = serde_json::to_string(&record.results.shapes).unwrap();
+    let fields = [
+        format!("medianNs={}i", computed.median.as_nanos()),
+        format!("meanNs={}i", computed.mean.as_nanos()),
+        format!("minNs={}i", computed.min.as_nanos()),
+        format!("maxNs={}i", computed.max.as_nanos()),
+        format!("varianceNs={}i", computed.variance.as_nanos()),
+        format!("numSamples={}i", record.results.raw.durations.len()),
+        format!("shapes=\"{}\"", shapes.replace('"', "\\\"")),
+    ]
+    .join(",");
+    // The timestamp of the record is in milliseconds, the line protocol expects nanoseconds
+    let timestamp = record.results.timestamp * 1_000_000;
+
+    format!("burnbench{tags} {fields} {timestamp}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::test_server::serve;
+    use serial_test::serial;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    fn record() -> BenchmarkRecord {
+        let mut record = BenchmarkRecord {
+            backend: "ndarray".to_string(),
+            device: "Cpu".to_string(),
+            feature: "ndarray".to_string(),
+            burn_version: "main".to_string(),
+            ..Default::default()
+        };
+        record.results.name = "matmul large".to_string();
+        record.results.shapes = vec![vec![8, 8]];
+        record.results.timestamp = 1710208069697;
+        record.results.computed.median = Duration::from_nanos(850);
+        record
+    }
+
+    #[test]
+    fn sinks_are_parsed_from_the_cli() {
+        let sink = SinkConfig::parse("http=http://localhost:8086/results")
+            .unwrap()
+            .with_headers(&[parse_header("X-Api-Key: secret").unwrap()]);
+        assert_eq!(
+            sink,
+            SinkConfig::Http {
+                url: "http://localhost:8086/results".to_string(),
+                headers: BTreeMap::from([("X-Api-Key".to_string(), "secret".to_string())]),
+            }
+        );
+        assert!(SinkConfig::parse("ftp=host").is_err());
+
+        let sink = SinkConfig::parse("jsonl=results.jsonl")
+            .unwrap()
+            .relative_to(Path::new("/work"));
+        assert_eq!(
+            sink,
+            SinkConfig::JsonLines {
+                path: PathBuf::from("/work/results.jsonl"),
+            }
+        );
+        let sink = SinkConfig::parse("jsonl=/tmp/results.jsonl")
+            .unwrap()
+            .relative_to(Path::new("/work"));
+        assert_eq!(
+            sink,
+            SinkConfig::JsonLines {
+                path: PathBuf::from("/tmp/results.jsonl"),
+            }
+        );
+        assert!(parse_header("no header").is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn sinks_are_read_from_the_env() {
+        unsafe {
+            std::env::remove_var(SINKS_ENV_VAR);
+        }
+        assert_eq!(SinkConfig::from_env(), Ok(vec![]));
+
+        unsafe {
+            std::env::set_var(
+                SINKS_ENV_VAR,
+                r#"[{"type": "jsonLines", "path": "results.jsonl"}]"#,
+            );
+        }
+        assert_eq!(
+            SinkConfig::from_env(),
+            Ok(vec![SinkConfig::JsonLines {
+                path: PathBuf::from("results.jsonl"),
+            }])
+        );
+
+        unsafe {
+            std::env::set_var(SINKS_ENV_VAR, r#"[{"type": "jsonl"}]"#);
+        }
+        assert!(SinkConfig::from_env().is_err());
+
+        unsafe {
+            std::env::remove_var(SINKS_ENV_VAR);
+        }
+    }
+
+    #[test]
+    fn http_sink_posts_json_records() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/results", listener.local_addr().unwrap());
+        let server = serve(listener, vec![204]);
+
+        let headers = BTreeMap::from([("X-Api-Key".to_string(), "secret".to_string())]);
+        HttpSink::new(&url, headers).write(&[record()]).unwrap();
+
+        let request = server.join().unwrap().remove(0);
+        assert!(request.headers.starts_with("post /results"));
+        assert!(request.headers.contains("x-api-key: secret"));
+        let posted: BenchmarkRecord = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(posted.results.name, "matmul large");
+    }
+
+    #[test]
+    fn influx_sink_posts_line_protocol() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/v2/write", listener.local_addr().unwrap());
+        let server = serve(listener, vec![204]);
+
+        InfluxSink::new(&url, BTreeMap::new())
+            .write(&[record()])
+            .unwrap();
+
+        let request = server.join().unwrap().remove(0);
+        assert_eq!(
+            request.body,
+            "burnbench,name=matmul\\ large,backend=ndarray,device=Cpu,feature=ndarray,burnVersion=main \
+             medianNs=850i,meanNs=0i,minNs=0i,maxNs=0i,varianceNs=0i,numSamples=0i,shapes=\"[[8,8]]\" \
+             1710208069697000000"
+        );
+    }
+
+    #[test]
+    fn json_lines_sink_appends_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = JsonLinesSink::new(dir.path().join("results").join("records.jsonl"));
+
+        sink.write(&[record()]).unwrap();
+        sink.write(&[record(), record()]).unwrap();
+
+        let content = fs::read_to_string(dir.path().join("results/records.jsonl")).unwrap();
+        assert_eq!(content.lines().count(), 3);
+    }
+}
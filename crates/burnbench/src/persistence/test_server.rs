@@ -0,0 +1,46 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread::JoinHandle;
+
+/// A request received by the test server.
+pub(crate) struct ReceivedRequest {
+    /// The request line and the headers, in lowercase.
+    pub(crate) headers: String,
+    pub(crate) body: String,
+}
+
+/// Answer each request with the next status and return the received requests.
+pub(crate) fn serve(listener: TcpListener, statuses: Vec<u16>) -> JoinHandle<Vec<ReceivedRequest>> {
+    std::thread::spawn(move || {
+        let mut requests = vec![];
+        for status in statuses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut headers = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                headers.push_str(&line.to_lowercase());
+            }
+            let length = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length: "))
+                .map(|length| length.trim().parse::<usize>().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let response = format!(
+                "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            requests.push(ReceivedRequest {
+                headers,
+                body: String::from_utf8(body).unwrap(),
+            });
+        }
+        requests
+    })
+}
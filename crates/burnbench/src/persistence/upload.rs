@@ -0,0 +1,424 @@
+use crate::BenchmarkRecord;
+use crate::auth::get_auth_header_value;
+use crate::storage::uploads_dir;
+
+use reqwest::StatusCode;
+use reqwest::header::{ACCEPT, AUTHORIZATION, HeaderMap, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How the failed uploads are retried, the delay between two attempts doubles after each
+/// attempt.
+#[derive(Debug, Clone)]
+pub(crate) struct RetryPolicy {
+    pub(crate) max_attempts: u32,
+    pub(crate) initial_delay: Duration,
+    pub(crate) max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the given retry, starting at 0 for the first retry.
+    fn delay(&self, retry: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay)
+    }
+
+    /// Call the function until it succeeds, fails with an error that is not transient, or the
+    /// maximum number of attempts is reached.
+    fn run<T>(&self, mut f: impl FnMut() -> Result<T, UploadError>) -> Result<T, UploadError> {
+        let mut retry = 0;
+        loop {
+            match f() {
+                Err(UploadError::Transient(_)) if retry + 1 < self.max_attempts => {
+                    std::thread::sleep(self.delay(retry));
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Failure of an upload.
+#[derive(Debug)]
+pub(crate) enum UploadError {
+    /// The server could not be reached or is unavailable, the upload is retried.
+    Transient(String),
+    /// The server refused the token.
+    Unauthorized(String),
+    /// The server refused the record.
+    Rejected(String),
+}
+
+impl UploadError {
+    /// Returns true if the upload can succeed later, once the server is available again or once
+    /// the token is refreshed.
+    fn can_be_queued(&self, refreshable: bool) -> bool {
+        match self {
+            Self::Transient(_) => true,
+            Self::Unauthorized(_) => refreshable,
+            Self::Rejected(_) => false,
+        }
+    }
+}
+
+impl Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Transient(err) | Self::Unauthorized(err) | Self::Rejected(err) => {
+                f.write_str(err)
+            }
+        }
+    }
+}
+
+/// Records that could not be uploaded, saved in the `uploads` directory of the data directory
+/// until they are sent with `burnbench upload --pending`.
+///
+/// The token is not saved, the pending uploads are sent with the token of the authenticated user.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PendingUpload {
+    pub(crate) url: String,
+    /// Time at which the upload failed, in milliseconds since the Unix epoch.
+    pub(crate) created_at: u128,
+    pub(crate) records: Vec<BenchmarkRecord>,
+}
+
+impl PendingUpload {
+    fn new(url: &str, records: &[BenchmarkRecord]) -> Self {
+        Self {
+            url: url.to_string(),
+            created_at: chrono::Utc::now().timestamp_millis() as u128,
+            records: records.to_vec(),
+        }
+    }
+
+    /// Save the upload in the given directory.
+    fn queue(&self, dir: &Path) -> Result<PathBuf, String> {
+        fs::create_dir_all(dir).map_err(|err| format!("cannot create {}: {err}", dir.display()))?;
+        let path = dir.join(format!("{}-{}.json", self.created_at, uuid::Uuid::new_v4()));
+        fs::write(&path, serde_json::to_string(self).unwrap())
+            .map_err(|err| format!("cannot write {}: {err}", path.display()))?;
+
+        Ok(path)
+    }
+
+    /// The uploads saved in the given directory, oldest first.
+    fn pending(dir: &Path) -> Vec<(PathBuf, Self)> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return vec![];
+        };
+
+        let mut paths = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let upload = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+                Some((path, upload))
+            })
+            .collect()
+    }
+}
+
+/// Outcome of the upload of the pending records.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct PendingSummary {
+    pub(crate) uploaded: usize,
+    pub(crate) rejected: usize,
+    pub(crate) remaining: usize,
+}
+
+/// Outcome of the upload of several records.
+#[derive(Default)]
+struct UploadOutcome {
+    /// Records that can be uploaded later, with the error of the first of them.
+    queued: Vec<BenchmarkRecord>,
+    queued_error: Option<UploadError>,
+    /// Number of records refused by the server, with the error of the last of them.
+    rejected: usize,
+    rejected_error: Option<UploadError>,
+}
+
+/// Upload the records one by one, retrying the transient failures.
+///
+/// The records that can be uploaded later are queued on disk and the returned error tells where,
+/// the records refused by the server are reported without being queued. The token is refreshable
+/// when the user can get a new one with `burnbench auth`.
+pub(crate) fn upload_records(
+    url: &str,
+    token: &str,
+    refreshable: bool,
+    records: &[BenchmarkRecord],
+) -> Result<(), String> {
+    upload_or_queue(
+        url,
+        token,
+        refreshable,
+        records,
+        &RetryPolicy::default(),
+        &uploads_dir(),
+    )
+}
+
+/// Upload the records queued by the failed uploads, the uploads that fail again stay queued.
+pub(crate) fn upload_pending(token: &str, refreshable: bool) -> PendingSummary {
+    upload_pending_in(token, refreshable, &RetryPolicy::default(), &uploads_dir())
+}
+
+fn upload_or_queue(
+    url: &str,
+    token: &str,
+    refreshable: bool,
+    records: &[BenchmarkRecord],
+    policy: &RetryPolicy,
+    dir: &Path,
+) -> Result<(), String> {
+    let outcome = upload_each(url, token, refreshable, records, policy);
+
+    let mut errors = vec![];
+    if let Some(err) = outcome.queued_error {
+        let path = PendingUpload::new(url, &outcome.queued).queue(dir)?;
+        errors.push(format!(
+            "{err}, {} records queued in {}",
+            outcome.queued.len(),
+            path.display()
+        ));
+    }
+    if let Some(err) = outcome.rejected_error {
+        errors.push(format!("{err}, {} records rejected", outcome.rejected));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+fn upload_pending_in(
+    token: &str,
+    refreshable: bool,
+    policy: &RetryPolicy,
+    dir: &Path,
+) -> PendingSummary {
+    let mut summary = PendingSummary::default();
+    for (path, mut upload) in PendingUpload::pending(dir) {
+        let outcome = upload_each(&upload.url, token, refreshable, &upload.records, policy);
+        summary.uploaded += upload.records.len() - outcome.queued.len() - outcome.rejected;
+        summary.rejected += outcome.rejected;
+        summary.remaining += outcome.queued.len();
+
+        if let Some(err) = outcome.rejected_error {
+            eprintln!(
+                "❌ {} records of {} were rejected ({err})",
+                outcome.rejected,
+                path.display()
+            );
+        }
+        if let Some(err) = outcome.queued_error {
+            eprintln!("❌ Failed to upload {} ({err})", path.display());
+            upload.records = outcome.queued;
+            fs::write(&path, serde_json::to_string(&upload).unwrap()).ok();
+        } else {
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    summary
+}
+
+/// Upload each record in its own request.
+///
+/// When a record cannot be uploaded for now, the next records would fail the same way and are
+/// queued without being sent.
+fn upload_each(
+    url: &str,
+    token: &str,
+    refreshable: bool,
+    records: &[BenchmarkRecord],
+    policy: &RetryPolicy,
+) -> UploadOutcome {
+    let mut outcome = UploadOutcome::default();
+    for (index, record) in records.iter().enumerate() {
+        match policy.run(|| upload_record(url, token, record)) {
+            Ok(()) => {}
+            Err(err) if err.can_be_queued(refreshable) => {
+                outcome.queued = records[index..].to_vec();
+                outcome.queued_error = Some(err);
+                break;
+            }
+            // The token is refused for every record.
+            Err(err @ UploadError::Unauthorized(_)) => {
+                outcome.rejected += records.len() - index;
+                outcome.rejected_error = Some(err);
+                break;
+            }
+            Err(err) => {
+                outcome.rejected += 1;
+                outcome.rejected_error = Some(err);
+            }
+        }
+    }
+
+    outcome
+}
+
+/// Post the record as a JSON object.
+fn upload_record(url: &str, token: &str, record: &BenchmarkRecord) -> Result<(), UploadError> {
+    let client = reqwest::blocking::Client::new();
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, "burnbench".parse().unwrap());
+    headers.insert(ACCEPT, "application/json".parse().unwrap());
+    headers.insert(
+        AUTHORIZATION,
+        get_auth_header_value(token)
+            .parse()
+            .map_err(|_| UploadError::Rejected("invalid auth token".to_string()))?,
+    );
+
+    let response = client
+        .post(url)
+        .headers(headers)
+        .json(record)
+        .send()
+        .map_err(|err| UploadError::Transient(err.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(())
+    } else if status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+    {
+        Err(UploadError::Transient(format!("status {status}")))
+    } else if status == StatusCode::UNAUTHORIZED {
+        Err(UploadError::Unauthorized(format!("status {status}")))
+    } else {
+        Err(UploadError::Rejected(format!("status {status}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::test_server::serve;
+    use std::net::TcpListener;
+
+    const TOKEN: &str = "ghu_token";
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        }
+    }
+
+    fn records() -> Vec<BenchmarkRecord> {
+        let mut record = BenchmarkRecord::default();
+        record.results.name = "unary".to_string();
+        vec![record.clone(), record]
+    }
+
+    #[test]
+    fn retry_delays_grow_exponentially() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(10), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn records_are_uploaded_one_per_request_after_transient_failures() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/benchmarks", listener.local_addr().unwrap());
+        let server = serve(listener, vec![503, 200, 200]);
+        let dir = tempfile::tempdir().unwrap();
+
+        upload_or_queue(&url, TOKEN, false, &records(), &policy(), dir.path()).unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 3);
+        for request in &requests[1..] {
+            let uploaded: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+            assert_eq!(uploaded["name"], "unary");
+        }
+        assert!(PendingUpload::pending(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn failed_uploads_are_queued_until_flushed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/benchmarks", listener.local_addr().unwrap());
+        let server = serve(listener, vec![500, 500, 500, 201, 201]);
+        let dir = tempfile::tempdir().unwrap();
+
+        let err =
+            upload_or_queue(&url, TOKEN, false, &records(), &policy(), dir.path()).unwrap_err();
+        assert!(err.starts_with("status 500"));
+        assert_eq!(PendingUpload::pending(dir.path()).len(), 1);
+
+        let summary = upload_pending_in(TOKEN, false, &policy(), dir.path());
+        assert_eq!(
+            summary,
+            PendingSummary {
+                uploaded: 2,
+                rejected: 0,
+                remaining: 0
+            }
+        );
+        assert!(PendingUpload::pending(dir.path()).is_empty());
+        assert_eq!(server.join().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn rejected_uploads_are_not_queued() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/benchmarks", listener.local_addr().unwrap());
+        let server = serve(listener, vec![400, 201]);
+        let dir = tempfile::tempdir().unwrap();
+
+        let err =
+            upload_or_queue(&url, TOKEN, true, &records(), &policy(), dir.path()).unwrap_err();
+        assert_eq!(err, "status 400 Bad Request, 1 records rejected");
+        assert_eq!(server.join().unwrap().len(), 2);
+        assert!(PendingUpload::pending(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn unauthorized_uploads_are_queued_when_the_token_can_be_refreshed() {
+        for (refreshable, queued) in [(true, 1), (false, 0)] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/benchmarks", listener.local_addr().unwrap());
+            let server = serve(listener, vec![401]);
+            let dir = tempfile::tempdir().unwrap();
+
+            assert!(
+                upload_or_queue(&url, TOKEN, refreshable, &records(), &policy(), dir.path())
+                    .is_err()
+            );
+            assert_eq!(server.join().unwrap().len(), 1);
+            assert_eq!(PendingUpload::pending(dir.path()).len(), queued);
+        }
+    }
+}
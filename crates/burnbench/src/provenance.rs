@@ -0,0 +1,129 @@
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Environment variable overriding the git commit of the benches.
+pub const GIT_HASH_ENV_VAR: &str = "BURN_BENCH_GIT_HASH";
+/// Environment variable overriding whether the working tree of the benches is dirty.
+pub const GIT_DIRTY_ENV_VAR: &str = "BURN_BENCH_GIT_DIRTY";
+
+/// Git commit saved in the records when none of the sources can provide it.
+pub const UNKNOWN_GIT_HASH: &str = "unknown";
+
+/// Version of the code of the benches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitProvenance {
+    /// Commit of the benches, `unknown` if it cannot be determined.
+    pub hash: String,
+    /// Whether the working tree had uncommitted changes, `None` if unknown.
+    pub dirty: Option<bool>,
+}
+
+/// A way to find the version of the code of the benches.
+pub trait ProvenanceSource {
+    /// The provenance given by this source, `None` if unavailable.
+    fn provenance(&self) -> Option<GitProvenance>;
+}
+
+/// Read the provenance from the `BURN_BENCH_GIT_HASH` and `BURN_BENCH_GIT_DIRTY` environment
+/// variables, e.g. when the benches are built from a tarball.
+pub struct EnvProvenance;
+
+/// Ask git for the commit of the current directory and the state of its working tree.
+pub struct GitCommandProvenance;
+
+impl ProvenanceSource for EnvProvenance {
+    fn provenance(&self) -> Option<GitProvenance> {
+        let hash = std::env::var(GIT_HASH_ENV_VAR)
+            .ok()
+            .filter(|hash| !hash.is_empty())?;
+        let dirty = std::env::var(GIT_DIRTY_ENV_VAR)
+            .ok()
+            .and_then(|dirty| parse_bool(&dirty));
+
+        Some(GitProvenance { hash, dirty })
+    }
+}
+
+impl ProvenanceSource for GitCommandProvenance {
+    fn provenance(&self) -> Option<GitProvenance> {
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+        };
+
+        let hash = git(&["rev-parse", "HEAD"])?.trim().to_string();
+        let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+            .map(|status| !status.trim().is_empty());
+
+        Some(GitProvenance { hash, dirty })
+    }
+}
+
+impl GitProvenance {
+    /// The provenance given by the first available source, or an unknown provenance.
+    pub fn resolve(sources: &[&dyn ProvenanceSource]) -> Self {
+        sources
+            .iter()
+            .find_map(|source| source.provenance())
+            .unwrap_or_else(|| Self {
+                hash: UNKNOWN_GIT_HASH.to_string(),
+                dirty: None,
+            })
+    }
+
+    /// The provenance of the benches of the current process, read from the environment variables,
+    /// then git.
+    ///
+    /// It is resolved once per process.
+    pub fn current() -> &'static Self {
+        static CURRENT: OnceLock<GitProvenance> = OnceLock::new();
+        CURRENT.get_or_init(|| Self::resolve(&[&EnvProvenance, &GitCommandProvenance]))
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedProvenance(Option<GitProvenance>);
+
+    impl ProvenanceSource for FixedProvenance {
+        fn provenance(&self) -> Option<GitProvenance> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn first_available_source_is_used() {
+        let provenance = GitProvenance {
+            hash: "02d37011ab4dc773286e5983c09cde61f95ba4b5".to_string(),
+            dirty: Some(true),
+        };
+        let resolved = GitProvenance::resolve(&[
+            &FixedProvenance(None),
+            &FixedProvenance(Some(provenance.clone())),
+            &FixedProvenance(None),
+        ]);
+        assert_eq!(resolved, provenance);
+    }
+
+    #[test]
+    fn provenance_falls_back_to_unknown() {
+        let resolved = GitProvenance::resolve(&[&FixedProvenance(None)]);
+
+        assert_eq!(resolved.hash, UNKNOWN_GIT_HASH);
+        assert_eq!(resolved.dirty, None);
+    }
+}
@@ -88,7 +88,7 @@ pub(crate) fn get_tokens() -> Option<Tokens> {
 pub(crate) fn get_username(access_token: &str) -> Result<UserInfo, Box<dyn Error>> {
     let client = reqwest::blocking::Client::new();
     let response = client
-        .get(format!("{TRACEL_CI_SERVER_BASE_URL}users/me"))
+        .get(Endpoints::current().server_url("users/me"))
         .header(reqwest::header::USER_AGENT, "burnbench")
         .header(reqwest::header::CONTENT_TYPE, "application/json")
         .header(
@@ -183,7 +183,7 @@ fn refresh_tokens(tokens: &Tokens) -> Option<Tokens> {
         println!("Refreshing token...");
         let client = reqwest::blocking::Client::new();
         let response = client
-            .post(format!("{TRACEL_CI_SERVER_BASE_URL}auth/refresh-token"))
+            .post(Endpoints::current().server_url("auth/refresh-token"))
             .header(reqwest::header::USER_AGENT, "burnbench")
             .header(reqwest::header::CONTENT_TYPE, "application/json")
             .header(
@@ -206,8 +206,7 @@ fn refresh_tokens(tokens: &Tokens) -> Option<Tokens> {
 
 /// Return the file path for the auth cache on disk
 fn get_auth_cache_file_path() -> PathBuf {
-    let home_dir = dirs::home_dir().expect("an home directory should exist");
-    let path_dir = home_dir.join(".cache").join("burn").join("burnbench");
+    let path_dir = crate::data_dir();
     #[cfg(test)]
     let path_dir = path_dir.join("test");
     let path = Path::new(&path_dir);
@@ -232,7 +231,7 @@ fn save_tokens(tokens: &Tokens) {
 #[cfg(test)]
 use serial_test::serial;
 
-use crate::{TRACEL_CI_SERVER_BASE_URL, runner::auth::github_device_flow::DeviceFlow};
+use crate::{Endpoints, runner::auth::github_device_flow::DeviceFlow};
 
 #[cfg(test)]
 mod tests {
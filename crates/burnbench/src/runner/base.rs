@@ -1,30 +1,65 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::builder::PossibleValuesParser;
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
-use std::fs;
+use std::cell::OnceCell;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
 use std::sync::{Arc, Mutex};
-use strum::{Display, EnumIter, IntoEnumIterator};
+use strum::{Display, EnumIter};
 
 use super::auth::Tokens;
+use crate::__private::BURN_COMMIT_ENV_VAR;
 use crate::endgroup;
+use crate::endpoints::set_endpoints;
 use crate::group;
 use crate::runner::workflow::send_output_results;
 use crate::runner::workflow::send_started_event;
+use crate::storage::{RetentionPolicy, Run, record_files, set_data_dir};
 use crate::system_info::BenchmarkSystemInfo;
-use crate::{BENCHMARK_WEBSITE_URL, TRACEL_CI_SERVER_BASE_URL};
+use crate::{
+    BackendDescriptor, BenchmarkCase, BenchmarkFilter, DEVICE_ENV_VAR, DUAL_TIMING_ENV_VAR,
+    DeviceSelector, DeviceSupport, ENVIRONMENT_ENV_VAR, Endpoints, EnvironmentChecks,
+    ExecutionSettings, FILTER_ENV_VAR, LIST_ENV_VAR, PendingSummary, SCHEMA_VERSION, SINKS_ENV_VAR,
+    SinkConfig, TIMING_METHOD_ENV_VAR, TimingMethod, available_backends, available_devices,
+    config_file, data_dir, get_backend, migrate_record_file, parse_header, parse_tag,
+    upload_pending, upload_records,
+};
 
 use super::auth::get_tokens;
 use super::auth::get_username;
+use super::bundle::Bundle;
 use super::dependency::Dependency;
-use super::processor::{CargoRunner, NiceProcessor, OutputProcessor, Profiling, VerboseProcessor};
+use super::processor::{
+    CargoRunner, CaseCollector, NiceProcessor, OutputProcessor, Profiling, VerboseProcessor,
+};
 use super::progressbar::RunnerProgressBar;
-use super::reports::{BenchmarkCollection, FailedBenchmark};
+use super::reports::{BenchmarkCollection, FailedBenchmark, ShapeFmt};
+use super::server::{SERVER_TOKENS_ENV_VAR, Server, parse_server_token};
+use super::suite::{BenchSuite, BenchTarget};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
+    /// Crates containing the benches, given by their directory or their Cargo.toml
+    ///
+    /// Can be repeated to run several suites, replaces the default suite when set.
+    #[clap(long = "suite", global = true)]
+    suites: Vec<PathBuf>,
+
+    /// Directory where the benchmark records are stored, overrides `BURNBENCH_HOME`
+    #[clap(long = "data-dir", global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Base URL of the benchmark server, overrides `BURN_BENCH_SERVER_URL` and the configuration
+    #[clap(long = "server-url", global = true)]
+    server_url: Option<String>,
+
+    /// Base URL of the website showing the shared results, overrides `BURN_BENCH_WEBSITE_URL` and
+    /// the configuration
+    #[clap(long = "website-url", global = true)]
+    website_url: Option<String>,
+
     #[clap(subcommand)]
     command: Commands,
 }
@@ -33,19 +68,116 @@ struct Args {
 enum Commands {
     /// Authenticate using GitHub
     Auth,
-    /// List all available backends
-    List,
+    /// List all available backends, or the benches of the crate
+    List(ListArgs),
     /// Runs benchmarks
-    Run(RunArgs),
+    Run(Box<RunArgs>),
+    /// Upgrade the saved benchmark records to the current format
+    Migrate,
+    /// Remove the saved runs according to a retention policy
+    Clean(CleanArgs),
+    /// Write the saved runs to a bundle that can be imported on another machine
+    Export(ExportArgs),
+    /// Import the runs of bundles created with the export command
+    Import(ImportArgs),
+    /// Upload the results whose sharing failed
+    Upload(UploadArgs),
+    /// Inspect the configuration
+    #[clap(subcommand)]
+    Config(ConfigCommands),
+    /// Run a self-hosted server receiving the shared results
+    Serve(ServeArgs),
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print the effective endpoints and where they come from
+    Show,
+}
+
+#[derive(Parser, Debug)]
+struct ExportArgs {
+    /// Path of the bundle to create
+    output: PathBuf,
+
+    /// Export the run with this name, can be repeated
+    #[clap(long = "run")]
+    runs: Vec<String>,
+
+    /// Only export the runs with this tag, given as `key=value`
+    #[clap(long = "tag", value_parser = parse_tag)]
+    tags: Vec<(String, String)>,
+
+    /// Only export this number of most recent runs
+    #[clap(long = "last")]
+    last: Option<usize>,
+}
+
+#[derive(Parser, Debug)]
+struct ImportArgs {
+    /// Bundles to import
+    #[clap(required = true)]
+    bundles: Vec<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct ServeArgs {
+    /// Address on which the server listens
+    #[clap(long = "address", default_value = "127.0.0.1:8000")]
+    address: String,
+
+    /// Token accepted by the server, given as `name=token`, can be repeated
+    ///
+    /// The tokens must start with `ghu_` or `github_pat_`. They can also be given as comma
+    /// separated pairs in the `BURN_BENCH_SERVER_TOKENS` environment variable.
+    #[clap(long = "token", value_parser = parse_server_token)]
+    tokens: Vec<(String, String)>,
+}
+
+#[derive(Parser, Debug)]
+struct UploadArgs {
+    /// Upload the results queued when their sharing failed
+    #[clap(long = "pending", required = true)]
+    pending: bool,
+}
+
+#[derive(Parser, Debug)]
+#[clap(group(ArgGroup::new("policy").required(true).multiple(true).args(["keep_last", "older_than", "tags"])))]
+struct CleanArgs {
+    /// Keep this number of most recent runs
+    #[clap(long = "keep-last")]
+    keep_last: Option<usize>,
+
+    /// Only remove the runs older than this number of days
+    #[clap(long = "older-than")]
+    older_than: Option<u64>,
+
+    /// Only remove the runs with this tag, given as `key=value`
+    #[clap(long = "tag", value_parser = parse_tag)]
+    tags: Vec<(String, String)>,
+
+    /// Print the runs that would be removed without removing them
+    #[clap(long = "dry-run")]
+    dry_run: bool,
 }
 
-/// Information about the crate to benchmark.
-#[derive(Debug)]
-struct CrateInfo {
-    /// The name of the crate that contains the benchmarks.
-    name: String,
-    /// The path from which the command burnbench will be run.
-    path: PathBuf,
+#[derive(Parser, Debug)]
+struct ListArgs {
+    /// List the benches declared in the crate Cargo.toml instead of the backends
+    #[clap(long = "benches")]
+    benches: bool,
+
+    /// Also list the cases of each bench, this requires to compile the benches
+    #[clap(long = "cases", requires = "benches")]
+    cases: bool,
+
+    /// Backend used to compile the benches when listing the cases
+    #[clap(short = 'B', long = "backend", default_value = "ndarray", value_parser = backend_value_parser())]
+    backend: String,
+
+    /// Only list the benchmark cases matching this regex
+    #[clap(short = 'f', long = "filter", requires = "cases")]
+    filter: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -59,13 +191,91 @@ struct RunArgs {
     verbose: bool,
 
     /// Space separated list of backends to include
-    #[clap(short = 'B', long = "backends", num_args(1..), required = true)]
-    backends: Vec<BackendValues>,
+    #[clap(short = 'B', long = "backends", num_args(1..), required = true, value_parser = backend_value_parser())]
+    backends: Vec<String>,
 
     /// Space separated list of benches to run
     #[clap(short = 'b', long = "benches", num_args(0..))]
     benches: Vec<String>,
 
+    /// Space separated list of devices to run the benches on
+    ///
+    /// A device is written `default`, `cpu`, `discrete:<index>`, `integrated:<index>` or
+    /// `virtual:<index>`. Use `all` to run on the CPU and every detected GPU, the devices
+    /// not supported by a backend are skipped.
+    #[clap(long = "device", num_args(1..), default_value = "default")]
+    devices: Vec<String>,
+
+    /// Only run the benchmark cases matching this regex
+    ///
+    /// The regex is matched against the case name followed by its shapes,
+    /// for instance `matmul-general-float32 8x1024x1024`.
+    #[clap(short = 'f', long = "filter")]
+    filter: Option<String>,
+
+    /// How the benches measure their durations: `system` or `device`
+    ///
+    /// Device timing measures the duration reported by the device, the benches that do not
+    /// support it are measured with the system clock. Benchmarks can also choose their own timing
+    /// method.
+    #[clap(long = "timing-method")]
+    timing_method: Option<TimingMethod>,
+
+    /// Measure every sample with both the system clock and the device
+    ///
+    /// The benches are executed twice per sample and the report shows the overhead of the
+    /// system timing over the device timing.
+    #[clap(long = "dual-timing")]
+    dual_timing: bool,
+
+    /// Refuse to run when the environment checks report a source of noise
+    ///
+    /// The CPU governor, turbo boost, load average, available memory and power supply are
+    /// checked before running the benches, by default only warnings are printed.
+    #[clap(long = "strict")]
+    strict: bool,
+
+    /// Tag the run with a `key=value` pair, can be repeated
+    ///
+    /// The tags are saved with the run and can be used to select the runs to remove with the
+    /// `clean` command.
+    #[clap(long = "tag", value_parser = parse_tag)]
+    tags: Vec<(String, String)>,
+
+    /// Describe the run with a note saved in its records
+    #[clap(long = "note")]
+    note: Option<String>,
+
+    /// Also write the results to a sink, can be repeated
+    ///
+    /// A sink is written `jsonl=<path>` to append the records to a JSON lines file,
+    /// `http=<url>` to post each record as JSON, or `influx=<url>` to post the records in the
+    /// InfluxDB line protocol. Sinks can also be configured with the `BURN_BENCH_SINKS`
+    /// environment variable.
+    #[clap(long = "sink", value_parser = SinkConfig::parse)]
+    sinks: Vec<SinkConfig>,
+
+    /// Header added to the requests of the HTTP sinks, written `Name: value`, can be repeated
+    #[clap(long = "sink-header", value_parser = parse_header)]
+    sink_headers: Vec<(String, String)>,
+
+    /// Pin the bench processes to a set of CPUs, e.g. `0-3,8` (Linux only)
+    #[clap(long = "cpu-set")]
+    cpu_set: Option<String>,
+
+    /// Comma separated list of thread counts used by the CPU backends and the BLAS libraries
+    ///
+    /// Sets `RAYON_NUM_THREADS`, `OMP_NUM_THREADS`, `OPENBLAS_NUM_THREADS`, `MKL_NUM_THREADS`
+    /// and `VECLIB_MAXIMUM_THREADS`. The benches running on the CPU are executed once per thread
+    /// count, `all` uses every logical core, e.g. `--threads 1,2,4,8,all`.
+    #[clap(long = "threads", value_delimiter = ',', num_args(1..), value_parser = parse_thread_count)]
+    threads: Vec<usize>,
+
+    /// Niceness of the bench processes, negative values raise their priority and usually
+    /// require root privileges (Linux only)
+    #[clap(long = "nice", allow_negative_numbers = true)]
+    nice: Option<i32>,
+
     /// One or more Burn versions, git branches, or commit hashes
     ///
     /// Default using @main.
@@ -96,68 +306,80 @@ enum BenchDType {
     BF16,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, Display, EnumIter)]
-enum BackendValues {
-    #[strum(to_string = "all")]
-    All,
-    #[strum(to_string = "candle-cpu")]
-    CandleCpu,
-    #[strum(to_string = "candle-cuda")]
-    CandleCuda,
-    #[strum(to_string = "candle-metal")]
-    CandleMetal,
-    #[strum(to_string = "cuda")]
-    Cuda,
-    #[strum(to_string = "cuda-fusion")]
-    CudaFusion,
-    #[cfg(target_os = "linux")]
-    #[strum(to_string = "rocm")]
-    Rocm,
-    #[cfg(target_os = "linux")]
-    #[strum(to_string = "rocm-fusion")]
-    RocmFusion,
-    #[strum(to_string = "ndarray")]
-    Ndarray,
-    #[strum(to_string = "ndarray-simd")]
-    NdarraySimd,
-    #[strum(to_string = "ndarray-blas-accelerate")]
-    NdarrayBlasAccelerate,
-    #[strum(to_string = "ndarray-blas-netlib")]
-    NdarrayBlasNetlib,
-    #[strum(to_string = "ndarray-blas-openblas")]
-    NdarrayBlasOpenblas,
-    #[strum(to_string = "tch-cpu")]
-    TchCpu,
-    #[strum(to_string = "tch-cuda")]
-    TchCuda,
-    #[strum(to_string = "tch-metal")]
-    TchMetal,
-    #[strum(to_string = "wgpu")]
-    Wgpu,
-    #[strum(to_string = "wgpu-fusion")]
-    WgpuFusion,
-    #[strum(to_string = "vulkan")]
-    Vulkan,
-    #[strum(to_string = "vulkan-fusion")]
-    VulkanFusion,
-    #[strum(to_string = "metal")]
-    Metal,
-    #[strum(to_string = "metal-fusion")]
-    MetalFusion,
+/// Parse a thread count from the command line, `all` is the number of logical cores.
+fn parse_thread_count(value: &str) -> Result<usize, String> {
+    if value == "all" {
+        return std::thread::available_parallelism()
+            .map(|cores| cores.get())
+            .map_err(|err| format!("cannot get the number of cores ({err})"));
+    }
+
+    match value.parse::<usize>() {
+        Ok(0) => Err("the number of threads must be at least 1".to_string()),
+        Ok(threads) => Ok(threads),
+        Err(_) => Err(format!("invalid thread count '{value}'")),
+    }
+}
+
+/// Parse a backend name from the command line, `all` selects every available backend.
+fn backend_value_parser() -> PossibleValuesParser {
+    PossibleValuesParser::new(
+        std::iter::once("all").chain(available_backends().map(|backend| backend.name)),
+    )
 }
 
 /// Execute burnbench on the provided crate located at the provided path.
+///
+/// The crate is expected at `<path>/crates/<name>`, see [execute_suites] for crates located
+/// anywhere else.
 pub fn execute<P: AsRef<Path>>(name: &str, path: P) {
-    let path: &Path = path.as_ref();
-    let info = CrateInfo {
-        name: name.to_string(),
-        path: path.join("crates").join(name),
-    };
+    execute_suites(&[path.as_ref().join("crates").join(name)]);
+}
+
+/// Execute burnbench on the provided benchmark suites.
+///
+/// A suite is a crate given by its directory or its `Cargo.toml` file. The suites passed with the
+/// `--suite` argument take precedence over the provided ones.
+pub fn execute_suites<P: AsRef<Path>>(paths: &[P]) {
     let args = Args::parse();
+    if let Some(data_dir) = args.data_dir {
+        set_data_dir(data_dir);
+    }
+    set_endpoints(args.server_url, args.website_url);
+    let paths: Vec<&Path> = if args.suites.is_empty() {
+        paths.iter().map(|path| path.as_ref()).collect()
+    } else {
+        args.suites.iter().map(|path| path.as_path()).collect()
+    };
+    // Only the commands executing the benches need the suites
+    let load_suites = || {
+        paths
+            .iter()
+            .map(BenchSuite::from_path)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| eprintln!("❌ Invalid benchmark suite ({err})"))
+            .ok()
+    };
+
     match args.command {
         Commands::Auth => command_auth(),
-        Commands::List => command_list(),
-        Commands::Run(run_args) => command_run(&info, run_args),
+        Commands::List(list_args) => {
+            if let Some(suites) = load_suites() {
+                command_list(&suites, list_args)
+            }
+        }
+        Commands::Run(run_args) => {
+            if let Some(suites) = load_suites() {
+                command_run(&suites, *run_args)
+            }
+        }
+        Commands::Migrate => command_migrate(),
+        Commands::Clean(clean_args) => command_clean(clean_args),
+        Commands::Export(export_args) => command_export(export_args),
+        Commands::Import(import_args) => command_import(import_args),
+        Commands::Upload(_) => command_upload(),
+        Commands::Config(ConfigCommands::Show) => command_config_show(),
+        Commands::Serve(serve_args) => command_serve(serve_args),
     }
 }
 
@@ -177,26 +399,271 @@ fn command_auth() {
     }
 }
 
-fn command_list() {
-    println!("Available Backends:");
-    for backend in BackendValues::iter() {
-        println!("- {}", backend);
+/// Rewrite the records of the cache written by older versions of burnbench with the current
+/// schema version.
+fn command_migrate() {
+    let mut migrated = 0;
+    for path in record_files() {
+        match migrate_record_file(&path) {
+            Ok(true) => migrated += 1,
+            Ok(false) => {}
+            Err(err) => eprintln!("❌ Failed to migrate the record ({err})"),
+        }
+    }
+    println!("Migrated {migrated} benchmark records to schema version {SCHEMA_VERSION}.");
+}
+
+/// Remove the runs selected by the retention policy with their records.
+fn command_clean(clean_args: CleanArgs) {
+    let policy = RetentionPolicy {
+        keep_last: clean_args.keep_last,
+        older_than: clean_args
+            .older_than
+            .map(|days| std::time::Duration::from_secs(days * 24 * 3600)),
+        tags: clean_args.tags,
+    };
+    let runs = Run::list();
+    let selected = policy.select(&runs, std::time::SystemTime::now());
+
+    for run in &selected {
+        if clean_args.dry_run {
+            println!("Would remove {}", run.path.display());
+        } else if let Err(err) = std::fs::remove_dir_all(&run.path) {
+            eprintln!("❌ Failed to remove {} ({err})", run.path.display());
+        }
+    }
+    if !clean_args.dry_run {
+        println!("Removed {} of {} runs.", selected.len(), runs.len());
+    }
+}
+
+/// Write the selected runs to a bundle.
+fn command_export(export_args: ExportArgs) {
+    let runs = Run::list();
+    let mut selected = runs
+        .iter()
+        .filter(|run| export_args.runs.is_empty() || export_args.runs.contains(&run.name()))
+        .filter(|run| run.has_tags(&export_args.tags))
+        .collect::<Vec<_>>();
+    if let Some(last) = export_args.last {
+        selected.drain(..selected.len().saturating_sub(last));
+    }
+    if selected.is_empty() {
+        eprintln!("❌ No run to export.");
+        return;
+    }
+
+    match Bundle::new(&selected).and_then(|bundle| {
+        bundle.save(&export_args.output)?;
+        Ok(bundle.num_records())
+    }) {
+        Ok(records) => println!(
+            "📦 Exported {} runs and {records} records to {}",
+            selected.len(),
+            export_args.output.display()
+        ),
+        Err(err) => eprintln!("❌ Failed to export the runs ({err})"),
+    }
+}
+
+/// Import the runs of the bundles in the data directory.
+fn command_import(import_args: ImportArgs) {
+    for path in &import_args.bundles {
+        match Bundle::load(path).and_then(Bundle::import) {
+            Ok(summary) => println!(
+                "📦 Imported {} records in {} runs from {}, skipped {} duplicates",
+                summary.records,
+                summary.runs,
+                path.display(),
+                summary.duplicates
+            ),
+            Err(err) => eprintln!("❌ Failed to import {} ({err})", path.display()),
+        }
+    }
+}
+
+fn command_upload() {
+    let Some(tokens) = get_tokens() else {
+        eprintln!("❌ Failed to upload the pending results (missing access token)");
+        return;
+    };
+
+    let summary = upload_pending(&tokens.access_token, tokens.refresh_token.is_some());
+    if summary == PendingSummary::default() {
+        println!("No pending results to upload.");
+    } else {
+        println!(
+            "📤 Uploaded {} pending records, {} rejected, {} remaining",
+            summary.uploaded, summary.rejected, summary.remaining
+        );
     }
 }
 
-fn command_run(info: &CrateInfo, mut run_args: RunArgs) {
+fn command_config_show() {
+    let endpoints = Endpoints::current();
+    println!("Data directory: {}", data_dir().display());
+    println!("Configuration file: {}", config_file().display());
+    println!(
+        "Server: {} ({})",
+        endpoints.server.url, endpoints.server.source
+    );
+    println!(
+        "Website: {} ({})",
+        endpoints.website.url, endpoints.website.source
+    );
+}
+
+fn command_serve(serve_args: ServeArgs) {
+    let mut users = serve_args.tokens;
+    if let Ok(tokens) = std::env::var(SERVER_TOKENS_ENV_VAR) {
+        for token in tokens.split(',').filter(|token| !token.trim().is_empty()) {
+            match parse_server_token(token.trim()) {
+                Ok(user) => users.push(user),
+                Err(err) => {
+                    eprintln!("❌ Invalid {SERVER_TOKENS_ENV_VAR} ({err})");
+                    return;
+                }
+            }
+        }
+    }
+    if users.is_empty() {
+        eprintln!("❌ The server requires at least one token, given with --token name=token");
+        return;
+    }
+
+    let server = match Server::bind(&serve_args.address, users) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("❌ Cannot listen on {} ({err})", serve_args.address);
+            return;
+        }
+    };
+    let address = server.local_addr().unwrap();
+    println!("🌐 Serving the results of {}", data_dir().display());
+    println!("   Server URL:  http://{address}/v1/");
+    println!("   Website URL: http://{address}/");
+    server.serve();
+}
+
+fn command_list(suites: &[BenchSuite], list_args: ListArgs) {
+    if !list_args.benches {
+        println!("Available Backends:");
+        println!("- all");
+        for backend in available_backends() {
+            println!("- {}", backend.name);
+        }
+        return;
+    }
+
+    if let Some(filter) = &list_args.filter
+        && let Err(err) = BenchmarkFilter::new(filter)
+    {
+        eprintln!("❌ Invalid filter '{filter}': {err}");
+        return;
+    }
+
+    for suite in suites {
+        println!("Available Benches of {}:", suite.name);
+        for target in suite.bench_targets() {
+            println!("- {}", target.name);
+            if !list_args.cases {
+                continue;
+            }
+
+            match list_cases(
+                suite,
+                &target,
+                &list_args.backend,
+                list_args.filter.as_deref(),
+            ) {
+                Ok(cases) => {
+                    for case in cases {
+                        let options = case
+                            .options
+                            .map(|options| format!(" {options}"))
+                            .unwrap_or_default();
+                        println!("    {} {}{options}", case.name, ShapeFmt::new(&case.shapes));
+                    }
+                }
+                Err(err) => {
+                    eprintln!("    ❌ Failed to list the cases ({err})");
+                }
+            }
+        }
+    }
+}
+
+/// Compile and execute the bench in listing mode to collect its cases.
+fn list_cases(
+    suite: &BenchSuite,
+    target: &BenchTarget,
+    backend: &str,
+    filter: Option<&str>,
+) -> Result<Vec<BenchmarkCase>, String> {
+    let name = &suite.name;
+    let mut features = format!("{name}/{backend},{name}/{}", BenchDType::F32);
+    for req_feature in target.required_features.iter() {
+        features += &format!(",{name}/{req_feature}");
+    }
+    let manifest_path = suite.manifest_path();
+    let args = [
+        "--bench",
+        &target.name,
+        "--features",
+        &features,
+        "--target-dir",
+        crate::BENCHMARKS_TARGET_DIR,
+        "--manifest-path",
+        manifest_path.to_str().unwrap(),
+    ];
+    let mut envs = vec![(LIST_ENV_VAR.to_string(), "true".to_string())];
+    if let Some(filter) = filter {
+        envs.push((FILTER_ENV_VAR.to_string(), filter.to_string()));
+    }
+
+    // The features of the backends are generated like for a run of the default version
+    let guard = Dependency::new("main")
+        .patch(suite.path.as_path())
+        .map_err(|err| format!("cannot patch {}: {err}", manifest_path.display()))?;
+    let collector = Arc::new(CaseCollector::default());
+    let runner = CargoRunner::new(&args, envs, collector.clone(), Profiling::Deactivated);
+    let status = runner.run();
+    core::mem::drop(guard);
+    let status = status.map_err(|err| err.to_string())?;
+
+    if !status.success() {
+        return Err(format!(
+            "run the bench with verbose enabled to see the error: cargo run --bin burnbench -- run --benches {} --backends {backend} --verbose",
+            target.name
+        ));
+    }
+
+    Ok(collector.cases())
+}
+
+fn command_run(suites: &[BenchSuite], mut run_args: RunArgs) {
     let mut tokens: Option<Tokens> = None;
     if run_args.share {
         tokens = get_tokens();
     }
     // collect benchmarks and benches to execute
-    let mut backends = run_args.backends.clone();
-    if backends.contains(&BackendValues::All) {
-        backends = BackendValues::iter()
-            .filter(|b| b != &BackendValues::All)
-            .collect();
-    }
-    let access_token = tokens.map(|t| t.access_token);
+    let backends: Vec<&BackendDescriptor> = if run_args.backends.iter().any(|b| b == "all") {
+        available_backends().collect()
+    } else {
+        run_args
+            .backends
+            .iter()
+            .filter_map(|name| get_backend(name))
+            .collect()
+    };
+
+    let backend_devices = match get_backend_devices(&backends, &run_args.devices) {
+        Ok(backend_devices) => backend_devices,
+        Err(err) => {
+            eprintln!("❌ {err}");
+            return;
+        }
+    };
 
     // Set the defaults
     if run_args.dtypes.is_empty() {
@@ -205,10 +672,101 @@ fn command_run(info: &CrateInfo, mut run_args: RunArgs) {
     if run_args.benches.is_empty() {
         run_args.benches.push("all".to_string());
     }
+    let suite_benches = match get_suite_benches(suites, &run_args.benches) {
+        Ok(suite_benches) => suite_benches,
+        Err(err) => {
+            eprintln!("❌ {err}");
+            return;
+        }
+    };
     if run_args.versions.is_empty() {
         run_args.versions.push("main".to_string());
     }
 
+    if let Some(filter) = &run_args.filter
+        && let Err(err) = BenchmarkFilter::new(filter)
+    {
+        eprintln!("❌ Invalid filter '{filter}': {err}");
+        return;
+    }
+
+    let environment = EnvironmentChecks::run();
+    for check in environment.warnings() {
+        println!(
+            "⚠️ Noisy environment, {} is {}: {}.",
+            check.name,
+            check.value.as_deref().unwrap_or("unknown"),
+            check.message.as_deref().unwrap_or_default()
+        );
+    }
+    if run_args.strict && !environment.is_clean() {
+        eprintln!("❌ Refusing to run the benchmarks in a noisy environment (--strict).");
+        return;
+    }
+
+    let execution = ExecutionSettings {
+        cpu_set: run_args.cpu_set,
+        threads: None,
+        nice: run_args.nice,
+    };
+    if let Err(err) = execution.validate() {
+        eprintln!("❌ Invalid execution settings: {err}");
+        return;
+    }
+    let targets = get_run_targets(&backend_devices, &execution, &run_args.threads);
+
+    // Environment variables forwarded to every bench
+    let mut envs = vec![(
+        ENVIRONMENT_ENV_VAR.to_string(),
+        serde_json::to_string(&environment).unwrap(),
+    )];
+    if let Some(filter) = &run_args.filter {
+        envs.push((FILTER_ENV_VAR.to_string(), filter.to_string()));
+    }
+    if let Some(timing_method) = run_args.timing_method {
+        envs.push((TIMING_METHOD_ENV_VAR.to_string(), timing_method.to_string()));
+    }
+    if run_args.dual_timing {
+        envs.push((DUAL_TIMING_ENV_VAR.to_string(), "true".to_string()));
+    }
+    let current_dir = match std::env::current_dir() {
+        Ok(dir) => dir,
+        Err(err) => {
+            eprintln!("❌ Cannot read the current directory ({err})");
+            return;
+        }
+    };
+    let env_sinks = match SinkConfig::from_env() {
+        Ok(sinks) => sinks,
+        Err(err) => {
+            eprintln!("❌ Invalid {SINKS_ENV_VAR} ({err})");
+            return;
+        }
+    };
+    let sinks = env_sinks
+        .into_iter()
+        .chain(run_args.sinks)
+        .map(|sink| {
+            sink.with_headers(&run_args.sink_headers)
+                .relative_to(&current_dir)
+        })
+        .collect::<Vec<_>>();
+    if !sinks.is_empty() {
+        envs.push((
+            SINKS_ENV_VAR.to_string(),
+            serde_json::to_string(&sinks).unwrap(),
+        ));
+    }
+
+    let run = match Run::create(run_args.tags.into_iter().collect(), run_args.note) {
+        Ok(run) => run,
+        Err(err) => {
+            eprintln!("❌ Cannot create the directory of the run ({err})");
+            return;
+        }
+    };
+    envs.extend(run.envs());
+
     let profiling = if run_args.profile {
         Profiling::Activated {
             ncu_path: run_args.ncu_path,
@@ -218,31 +776,157 @@ fn command_run(info: &CrateInfo, mut run_args: RunArgs) {
         Profiling::Deactivated
     };
     run_backend_comparison_benchmarks(
-        info,
-        &run_args.benches,
-        &backends,
+        &suite_benches,
+        &targets,
         &run_args.versions,
         &run_args.dtypes,
-        access_token.as_deref(),
+        &envs,
+        run,
+        tokens.as_ref(),
         run_args.verbose,
         &profiling,
     );
 }
 
-fn run_backend_comparison_benchmarks(
-    info: &CrateInfo,
+/// Select the benches to run in each suite, `all` runs every bench of every suite.
+fn get_suite_benches<'a>(
+    suites: &'a [BenchSuite],
     benches: &[String],
-    backends: &[BackendValues],
+) -> Result<Vec<(&'a BenchSuite, Vec<String>)>, String> {
+    if benches.iter().any(|bench| bench == "all") {
+        return Ok(suites
+            .iter()
+            .map(|suite| (suite, vec!["all".to_string()]))
+            .collect());
+    }
+
+    if suites.len() == 1 {
+        return Ok(vec![(&suites[0], benches.to_vec())]);
+    }
+
+    let mut suite_benches = suites
+        .iter()
+        .map(|suite| (suite, vec![]))
+        .collect::<Vec<_>>();
+    for bench in benches {
+        let mut found = false;
+        for (suite, suite_benches) in suite_benches.iter_mut() {
+            if suite.has_bench(bench) {
+                suite_benches.push(bench.clone());
+                found = true;
+            }
+        }
+        if !found {
+            return Err(format!("No suite declares the bench '{bench}'"));
+        }
+    }
+    suite_benches.retain(|(_, benches)| !benches.is_empty());
+
+    Ok(suite_benches)
+}
+
+/// A backend executed on a device with the given settings.
+struct RunTarget {
+    backend: &'static BackendDescriptor,
+    device: DeviceSelector,
+    execution: ExecutionSettings,
+}
+
+/// Apply the execution settings to each backend and device, the targets running on the CPU are
+/// repeated for each thread count.
+fn get_run_targets(
+    backend_devices: &[(&'static BackendDescriptor, DeviceSelector)],
+    execution: &ExecutionSettings,
+    threads: &[usize],
+) -> Vec<RunTarget> {
+    let mut threads = threads.to_vec();
+    threads.sort();
+    threads.dedup();
+
+    let mut targets = vec![];
+    for (backend, device) in backend_devices {
+        let on_cpu = backend.devices == DeviceSupport::Cpu || *device == DeviceSelector::Cpu;
+        let thread_counts = if on_cpu && !threads.is_empty() {
+            threads.iter().map(|threads| Some(*threads)).collect()
+        } else {
+            vec![None]
+        };
+
+        for threads in thread_counts {
+            targets.push(RunTarget {
+                backend,
+                device: *device,
+                execution: ExecutionSettings {
+                    threads,
+                    ..execution.clone()
+                },
+            });
+        }
+    }
+
+    targets
+}
+
+/// Combine each backend with the selected devices it supports.
+fn get_backend_devices(
+    backends: &[&'static BackendDescriptor],
+    devices: &[String],
+) -> Result<Vec<(&'static BackendDescriptor, DeviceSelector)>, String> {
+    let all = devices.iter().any(|device| device == "all");
+    let devices = if all {
+        vec![]
+    } else {
+        devices
+            .iter()
+            .map(|device| {
+                device
+                    .parse::<DeviceSelector>()
+                    .map_err(|err| format!("Invalid device '{device}': {err}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let mut targets = vec![];
+    for backend in backends {
+        if all {
+            for device in available_devices(backend) {
+                targets.push((*backend, device));
+            }
+            continue;
+        }
+
+        for device in devices.iter() {
+            if device.is_supported(backend.devices) {
+                targets.push((*backend, *device));
+            } else {
+                println!(
+                    "⚠️ Skipping backend {} which does not support the {device} device.",
+                    backend.name
+                );
+            }
+        }
+    }
+
+    Ok(targets)
+}
+
+fn run_backend_comparison_benchmarks(
+    suite_benches: &[(&BenchSuite, Vec<String>)],
+    targets: &[RunTarget],
     versions: &[String],
     dtypes: &[BenchDType],
-    token: Option<&str>,
+    envs: &[(String, String)],
+    run: Run,
+    tokens: Option<&Tokens>,
     verbose: bool,
     profiling: &Profiling,
 ) {
-    let mut report_collection = BenchmarkCollection::default();
+    let token = tokens.map(|tokens| tokens.access_token.as_str());
+    let mut report_collection = BenchmarkCollection::new(run);
     let inputs_file = std::env::var("WEBHOOK_INPUTS_FILE");
     let emit_started_webhook = std::env::var("BURN_BENCH_EMIT_STARTED_WEBHOOK").ok().map_or(false, |v| v == "true");
-    let total_count: u64 = (backends.len() * benches.len() * versions.len() * dtypes.len())
+    let bench_count: usize = suite_benches.iter().map(|(_, benches)| benches.len()).sum();
+    let total_count: u64 = (targets.len() * bench_count * versions.len() * dtypes.len())
         .try_into()
         .unwrap();
     let runner_pb: Option<Arc<Mutex<RunnerProgressBar>>> = if verbose {
@@ -257,25 +941,40 @@ fn run_backend_comparison_benchmarks(
     // Iterate through every combination of benchmark and backend
     println!("\nBenchmarking Burn @ {versions:?}");
     for version in versions.iter() {
-        for backend in backends.iter() {
-            for bench in benches.iter() {
+        // Resolved by the first bench of the version, the commit is the same for the others
+        let burn_commit = OnceCell::new();
+        for target in targets.iter() {
+            let (backend, device) = (target.backend, &target.device);
+            let mut envs = envs.to_vec();
+            envs.extend(target.execution.envs());
+            for (suite, bench) in suite_benches
+                .iter()
+                .flat_map(|(suite, benches)| benches.iter().map(move |bench| (*suite, bench)))
+            {
                 for dtype in dtypes.iter() {
                     let bench_str = bench.to_string();
-                    let backend_str = backend.to_string();
-                    let url = format!("{TRACEL_CI_SERVER_BASE_URL}benchmarks");
+                    let backend_str = backend.name.to_string();
 
                     if verbose {
-                        group!("Running benchmarks: {bench_str}@{backend_str}-{dtype}");
+                        let threads = target
+                            .execution
+                            .threads
+                            .map(|threads| format!(" with {threads} threads"))
+                            .unwrap_or_default();
+                        group!(
+                            "Running benchmarks: {bench_str}@{backend_str}-{dtype} on {device}{threads}"
+                        );
                     }
                     let status = run_cargo(
-                        info,
+                        suite,
                         &bench_str,
                         &backend_str,
+                        device,
                         dtype,
-                        &url,
-                        token,
+                        &envs,
                         &runner_pb,
                         version,
+                        &burn_commit,
                         profiling,
                     );
                     let success = status.unwrap().success();
@@ -289,8 +988,11 @@ fn run_backend_comparison_benchmarks(
                             pb.lock().unwrap().failed_inc();
                         }
                         report_collection.push_failed_benchmark(FailedBenchmark {
+                            suite: suite.path.clone(),
                             bench: bench_str.clone(),
                             backend: backend_str.clone(),
+                            device: *device,
+                            threads: target.execution.threads,
                         })
                     }
                     if verbose {
@@ -306,8 +1008,41 @@ fn run_backend_comparison_benchmarks(
     }
 
     let collection = report_collection.load_records();
-    let table = collection.get_ascii_table();
+    // The records of the run are shared once all the benches are executed
+    if let Some(tokens) = tokens {
+        println!("Sharing results...");
+        let url = Endpoints::current().server_url("benchmarks");
+        let refreshable = tokens.refresh_token.is_some();
+        match upload_records(
+            &url,
+            &tokens.access_token,
+            refreshable,
+            collection.records(),
+        ) {
+            Ok(()) => println!("Results shared successfully."),
+            Err(err) => eprintln!(
+                "❌ Failed to share results ({err}), upload them later with `burnbench upload --pending`"
+            ),
+        }
+    }
+    let mut table = collection.get_ascii_table();
+    if let Some(scaling_table) = collection.get_scaling_table() {
+        table.push_str(&format!("\n\nThread scaling:\n{scaling_table}"));
+    }
     let mut output_results = table.clone();
+    let run = collection.run();
+    output_results.push_str(&format!("\n\n🏷️ Run {}", run.id));
+    if !run.tags.is_empty() {
+        let tags = run
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>();
+        output_results.push_str(&format!(" [{}]", tags.join(", ")));
+    }
+    if let Some(note) = &run.note {
+        output_results.push_str(&format!(": {note}"));
+    }
     let share_link = web_results_url(token, versions);
     if let Some(ref url) = share_link {
         output_results.push_str(&format!("\n\n📊 Browse results at {}", url));
@@ -319,44 +1054,16 @@ fn run_backend_comparison_benchmarks(
     }
 }
 
-fn get_required_features(info: &CrateInfo, target_bench: &str) -> Vec<String> {
-    let cargo_file_path = Path::new(&info.path).join("Cargo.toml");
-
-    let content = fs::read_to_string(&cargo_file_path).expect("Failed to read Cargo.toml");
-    let parsed: toml::Value = content.parse().expect("Invalid TOML");
-
-    let benches = parsed.get("bench").and_then(|b| b.as_array()).unwrap();
-
-    for bench in benches {
-        let name = bench
-            .get("name")
-            .and_then(|v| v.as_str())
-            .unwrap_or_default();
-        if name == target_bench {
-            if let Some(features) = bench.get("required-features").and_then(|f| f.as_array()) {
-                let feature_list: Vec<String> = features
-                    .iter()
-                    .filter_map(|v| v.as_str())
-                    .map(String::from)
-                    .collect();
-                return feature_list;
-            }
-            return vec![];
-        }
-    }
-
-    vec![]
-}
-
 fn run_cargo(
-    info: &CrateInfo,
+    suite: &BenchSuite,
     bench: &str,
     backend: &str,
+    device: &DeviceSelector,
     dtype: &BenchDType,
-    url: &str,
-    token: Option<&str>,
+    envs: &[(String, String)],
     progress_bar: &Option<Arc<Mutex<RunnerProgressBar>>>,
     version: &str,
+    burn_commit: &OnceCell<Option<String>>,
     profile: &Profiling,
 ) -> io::Result<ExitStatus> {
     let processor: Arc<dyn OutputProcessor> = if let Some(pb) = progress_bar {
@@ -372,11 +1079,11 @@ fn run_cargo(
     let dependency = Dependency::new(&dependency_version);
     let mut features = String::new();
 
-    let guard = dependency.patch(info.path.as_path()).unwrap();
-    let name = &info.name;
+    let guard = dependency.patch(suite.path.as_path()).unwrap();
+    let name = &suite.name;
     features += &format!("{name}/{backend},{name}/{dtype}");
 
-    for req_feature in get_required_features(info, bench) {
+    for req_feature in suite.required_features(bench) {
         features += &format!(",{}", req_feature);
     }
 
@@ -386,10 +1093,11 @@ fn run_cargo(
         features += ",legacy-v17";
     }
 
-    for req_feature in get_required_features(info, bench) {
+    for req_feature in suite.required_features(bench) {
         features += &format!(",{name}/{req_feature}");
     }
 
+    let manifest_path = suite.manifest_path();
     let mut args = if bench == "all" {
         vec![
             "--benches",
@@ -408,20 +1116,17 @@ fn run_cargo(
             crate::BENCHMARKS_TARGET_DIR,
         ]
     };
+    args.push("--manifest-path");
+    args.push(manifest_path.to_str().unwrap());
 
-    if let Some(t) = token {
-        args.push("--");
-        args.push("--sharing-url");
-        args.push(url);
-        args.push("--sharing-token");
-        args.push(t);
-    }
-    let runner = CargoRunner::new(
-        &args,
-        vec![("BURN_BENCH_BURN_VERSION".to_string(), version.to_string())],
-        processor,
-        profile.clone(),
-    );
+    let mut envs = envs.to_vec();
+    envs.push(("BURN_BENCH_BURN_VERSION".to_string(), version.to_string()));
+    // Saved by the benches with their records
+    if let Some(commit) = burn_commit.get_or_init(|| dependency.resolve_commit(&suite.path)) {
+        envs.push((BURN_COMMIT_ENV_VAR.to_string(), commit.clone()));
+    }
+    envs.push((DEVICE_ENV_VAR.to_string(), device.to_string()));
+    let runner = CargoRunner::new(&args, envs, processor, profile.clone());
     let status = runner.run();
 
     core::mem::drop(guard);
@@ -447,11 +1152,65 @@ fn web_results_url(token: Option<&str>, versions: &[String]) -> Option<String> {
             let encoded_os = utf8_percent_encode(&sysinfo.os.name, NON_ALPHANUMERIC).to_string();
             let versions = utf8_percent_encode(&versions.join(","), NON_ALPHANUMERIC).to_string();
 
-            return Some(format!(
-                "{}benchmarks/community-benchmarks?user={}&sysHardware=Any&os={}&burnVersions={}",
-                BENCHMARK_WEBSITE_URL, user.nickname, encoded_os, versions
-            ));
+            return Some(Endpoints::current().website_url(&format!(
+                "benchmarks/community-benchmarks?user={}&sysHardware=Any&os={}&burnVersions={}",
+                user.nickname, encoded_os, versions
+            )));
         }
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BACKENDS;
+    use std::collections::BTreeSet;
+    use std::fs;
+
+    #[test]
+    fn cases_are_listed_on_a_suite_without_backend_features() {
+        let dir = tempfile::tempdir().unwrap();
+        // Stand-in for burn with the features enabled by the backends
+        let burn_features = BACKENDS
+            .iter()
+            .flat_map(|backend| backend.features_for(None))
+            .filter_map(|feature| feature.strip_prefix("burn/"))
+            .map(|feature| format!("{feature} = []\n"))
+            .collect::<BTreeSet<_>>();
+        fs::create_dir_all(dir.path().join("burn/src")).unwrap();
+        fs::write(
+            dir.path().join("burn/Cargo.toml"),
+            format!(
+                "[package]\nname = \"burn\"\nedition = \"2021\"\n\n[features]\n{}",
+                burn_features.into_iter().collect::<String>()
+            ),
+        )
+        .unwrap();
+        fs::write(dir.path().join("burn/src/lib.rs"), "").unwrap();
+
+        let manifest = "[workspace]\n\n[package]\nname = \"my-benches\"\nedition = \"2021\"\n\n[dependencies.burn]\npath = \"burn\"\n\n[features]\nf32 = []\n\n[[bench]]\nname = \"unary\"\nharness = false\n";
+        fs::write(dir.path().join("Cargo.toml"), manifest).unwrap();
+        fs::create_dir_all(dir.path().join("benches")).unwrap();
+        fs::write(
+            dir.path().join("benches/unary.rs"),
+            format!(
+                "fn main() {{ println!(\"{}{{}}\", r#\"{{\"name\":\"unary\",\"shapes\":[[32]],\"options\":null}}\"#); }}",
+                crate::LIST_CASE_PREFIX
+            ),
+        )
+        .unwrap();
+
+        let suite = BenchSuite::from_path(dir.path()).unwrap();
+        let target = suite.bench_targets().remove(0);
+        let cases = list_cases(&suite, &target, "ndarray", None).unwrap();
+
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].name, "unary");
+        assert_eq!(cases[0].shapes, vec![vec![32]]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("Cargo.toml")).unwrap(),
+            manifest
+        );
+    }
+}
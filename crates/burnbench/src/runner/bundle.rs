@@ -0,0 +1,259 @@
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::migrate_record;
+use crate::storage::{Run, RunMetadata, record_files};
+
+/// Version of the format of the bundles.
+const BUNDLE_VERSION: u32 = 1;
+
+/// Keys of a record identifying the execution of a benchmark.
+const IDENTITY_KEYS: [&str; 9] = [
+    "name",
+    "backend",
+    "device",
+    "feature",
+    "burnVersion",
+    "gitHash",
+    "options",
+    "shapes",
+    "timestamp",
+];
+
+/// Self-contained archive of runs, saved as gzip compressed JSON.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Bundle {
+    version: u32,
+    /// Time at which the bundle was created, in milliseconds since the Unix epoch.
+    created_at: u128,
+    runs: Vec<BundledRun>,
+}
+
+/// A run of a [Bundle] with its records, which include the system information, and its other
+/// files like logs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BundledRun {
+    name: String,
+    metadata: RunMetadata,
+    records: Vec<Value>,
+    files: BTreeMap<String, String>,
+}
+
+/// Outcome of the import of a bundle.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ImportSummary {
+    pub(crate) runs: usize,
+    pub(crate) records: usize,
+    pub(crate) duplicates: usize,
+}
+
+impl Bundle {
+    /// Bundle the given runs.
+    pub(crate) fn new(runs: &[&Run]) -> Result<Self, String> {
+        let runs = runs
+            .iter()
+            .map(|run| BundledRun::new(run))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            version: BUNDLE_VERSION,
+            created_at: chrono::Utc::now().timestamp_millis() as u128,
+            runs,
+        })
+    }
+
+    /// Bundle records received from another machine in the given run.
+    pub(crate) fn from_records(metadata: RunMetadata, records: Vec<Value>) -> Self {
+        Self {
+            version: BUNDLE_VERSION,
+            created_at: chrono::Utc::now().timestamp_millis() as u128,
+            runs: vec![BundledRun {
+                name: metadata.dir_name(),
+                metadata,
+                records,
+                files: BTreeMap::new(),
+            }],
+        }
+    }
+
+    /// Number of records of the bundle.
+    pub(crate) fn num_records(&self) -> usize {
+        self.runs.iter().map(|run| run.records.len()).sum()
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<(), String> {
+        let file = fs::File::create(path)
+            .map_err(|err| format!("cannot create {}: {err}", path.display()))?;
+        let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+        serde_json::to_writer(&mut encoder, self)
+            .map_err(io::Error::from)
+            .and_then(|_| encoder.finish()?.flush())
+            .map_err(|err| format!("cannot write {}: {err}", path.display()))
+    }
+
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let file =
+            fs::File::open(path).map_err(|err| format!("cannot open {}: {err}", path.display()))?;
+        let bundle: Self = serde_json::from_reader(GzDecoder::new(BufReader::new(file)))
+            .map_err(|err| format!("invalid bundle {}: {err}", path.display()))?;
+        if bundle.version > BUNDLE_VERSION {
+            return Err(format!(
+                "the bundle {} was created by a newer version of burnbench",
+                path.display()
+            ));
+        }
+
+        Ok(bundle)
+    }
+
+    /// Import the runs of the bundle in the data directory, skipping the records already saved.
+    pub(crate) fn import(self) -> Result<ImportSummary, String> {
+        self.import_with(&mut saved_records())
+    }
+
+    /// Import the runs of the bundle, skipping the records whose identity is known. The identities
+    /// of the imported records are added to the known ones.
+    pub(crate) fn import_with(self, known: &mut HashSet<String>) -> Result<ImportSummary, String> {
+        let mut summary = ImportSummary::default();
+        for bundled in self.runs {
+            let mut records = vec![];
+            let mut identities = HashSet::new();
+            for record in bundled.records {
+                let record = migrate_record(record)?;
+                let identity = record_identity(&record);
+                if known.contains(&identity) || !identities.insert(identity.clone()) {
+                    summary.duplicates += 1;
+                } else {
+                    records.push((identity, record));
+                }
+            }
+            if records.is_empty() {
+                continue;
+            }
+
+            let run = Run::open_or_create(&bundled.name, bundled.metadata)
+                .map_err(|err| format!("cannot create the run {}: {err}", bundled.name))?;
+            for (identity, record) in records {
+                let name = record["name"].as_str().unwrap_or("unknown");
+                let timestamp = record["timestamp"].as_u64().unwrap_or(0) as u128;
+                let path = run.record_path(name, timestamp);
+                fs::write(&path, serde_json::to_string_pretty(&record).unwrap())
+                    .map_err(|err| format!("cannot write {}: {err}", path.display()))?;
+                known.insert(identity);
+                summary.records += 1;
+            }
+            for (name, content) in bundled.files {
+                // Only keep the file name to stay in the directory of the run
+                let Some(name) = Path::new(&name).file_name() else {
+                    continue;
+                };
+                let path = run.path.join(name);
+                if !path.exists() {
+                    fs::write(&path, content)
+                        .map_err(|err| format!("cannot write {}: {err}", path.display()))?;
+                }
+            }
+            summary.runs += 1;
+        }
+
+        Ok(summary)
+    }
+}
+
+impl BundledRun {
+    fn new(run: &Run) -> Result<Self, String> {
+        let read = |path: &Path| {
+            fs::read(path).map_err(|err| format!("cannot read {}: {err}", path.display()))
+        };
+
+        let mut records = vec![];
+        for path in run.record_files() {
+            let record = serde_json::from_slice(&read(&path)?)
+                .map_err(|err| format!("invalid record {}: {err}", path.display()))?;
+            records.push(record);
+        }
+
+        let mut files = BTreeMap::new();
+        for path in run.other_files() {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            files.insert(name, String::from_utf8_lossy(&read(&path)?).to_string());
+        }
+
+        Ok(Self {
+            name: run.name(),
+            metadata: run.metadata.clone(),
+            records,
+            files,
+        })
+    }
+}
+
+/// Identities of the records saved in the data directory.
+pub(crate) fn saved_records() -> HashSet<String> {
+    record_files()
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|content| serde_json::from_str::<Value>(&content).ok())
+        .map(|record| record_identity(&record))
+        .collect()
+}
+
+/// Identity of a serialized record: two records with the same identity come from the same
+/// execution of a benchmark.
+fn record_identity(record: &Value) -> String {
+    let identity = IDENTITY_KEYS
+        .iter()
+        .map(|key| record.get(key).cloned().unwrap_or(Value::Null))
+        .collect::<Vec<_>>();
+    serde_json::to_string(&identity).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn records_are_identified_by_their_execution() {
+        let record = json!({"name": "unary", "backend": "ndarray", "timestamp": 1, "median": 10});
+        let remeasured =
+            json!({"name": "unary", "backend": "ndarray", "timestamp": 2, "median": 10});
+        let migrated =
+            json!({"name": "unary", "backend": "ndarray", "timestamp": 1, "medianNs": 10});
+
+        assert_ne!(record_identity(&record), record_identity(&remeasured));
+        assert_eq!(record_identity(&record), record_identity(&migrated));
+    }
+
+    #[test]
+    fn bundles_roundtrip_through_gzip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.burnbench");
+        let bundle = Bundle {
+            version: BUNDLE_VERSION,
+            created_at: 1,
+            runs: vec![BundledRun {
+                name: "20250101-000000-000".to_string(),
+                metadata: RunMetadata::default(),
+                records: vec![json!({"name": "unary"})],
+                files: BTreeMap::from([("run.log".to_string(), "logs".to_string())]),
+            }],
+        };
+
+        bundle.save(&path).unwrap();
+        let loaded = Bundle::load(&path).unwrap();
+
+        assert_eq!(loaded.num_records(), 1);
+        assert_eq!(loaded.runs[0].name, "20250101-000000-000");
+        assert_eq!(loaded.runs[0].files["run.log"], "logs");
+    }
+}
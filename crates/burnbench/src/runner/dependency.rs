@@ -1,6 +1,10 @@
 use regex::Regex;
 use semver::Version;
+
+use super::suite::workspace_root;
+use crate::BACKENDS;
 use std::io::Write;
+use std::process::{Command, Stdio};
 use std::time::Duration;
 use std::{
     fs::OpenOptions,
@@ -28,6 +32,49 @@ impl Dependency {
             Self::Git(git_ref)
         }
     }
+
+    /// Resolve the commit of burn used by the suite once its dependency is patched.
+    ///
+    /// The dependencies are fetched to update the `Cargo.lock` file of the workspace before the
+    /// benches are built. Returns `None` when burn is a crate or a local dependency.
+    pub(crate) fn resolve_commit(&self, suite: &Path) -> Option<String> {
+        let Self::Git(_) = self else {
+            return None;
+        };
+
+        let status = Command::new("cargo")
+            .arg("fetch")
+            .arg("--manifest-path")
+            .arg(suite.join("Cargo.toml"))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .ok()?;
+        if !status.success() {
+            return None;
+        }
+
+        let lockfile = std::fs::read_to_string(workspace_root(suite).join("Cargo.lock")).ok()?;
+        burn_commit_from_lockfile(&lockfile)
+    }
+}
+
+/// Resolve the commit of the `burn` git dependency from the content of a `Cargo.lock` file.
+///
+/// Returns `None` when burn is a crate or a local dependency.
+fn burn_commit_from_lockfile(lockfile: &str) -> Option<String> {
+    let lockfile = lockfile.parse::<toml::Table>().ok()?;
+    lockfile
+        .get("package")?
+        .as_array()?
+        .iter()
+        .filter(|package| package.get("name").and_then(|name| name.as_str()) == Some("burn"))
+        .find_map(|package| {
+            // Git sources look like `git+https://github.com/tracel-ai/burn?branch=main#<commit>`
+            let source = package.get("source")?.as_str()?;
+            let (_, commit) = source.strip_prefix("git+")?.rsplit_once('#')?;
+            Some(commit.to_string())
+        })
 }
 
 #[allow(dead_code)] // Used to keep things.
@@ -123,7 +170,7 @@ impl DependencyContent {
         }
 
         if burn_in_workspace {
-            let cargo_file_path = Path::new(".").join("Cargo.toml");
+            let cargo_file_path = workspace_root(base_path).join("Cargo.toml");
             let content = std::fs::read_to_string(&cargo_file_path)?;
             workspace = Some(content);
             workspace_path = Some(cargo_file_path);
@@ -156,62 +203,74 @@ impl Dependency {
         let burn_dir = std::env::var("BURN_BENCH_BURN_DIR").unwrap_or("../../burn/".into());
         let content_original = DependencyContent::from_path(base_path)?;
 
-        let content = match self {
+        let mut content = match self {
             Dependency::Local => self.update_burn_local(&content_original, &burn_dir),
             Dependency::Crate(version) => self.update_burn_version(&content_original, version),
             Dependency::Git(version) => self.update_burn_git(&content_original, version),
         }?;
 
+        let version = match self {
+            Dependency::Crate(version) => Some(version),
+            _ => None,
+        };
+        let benches = content
+            .benches
+            .take()
+            .unwrap_or_else(|| content_original.benches.clone());
+        content.benches = Some(Self::update_feature_flags(version, benches));
+
         let guard = content.create_guard(&content_original);
         content.perform_update(&content_original)?;
 
         Ok(guard)
     }
 
-    fn update_feature_flags(version: &Version, content: String) -> String {
-        if version < &Version::new(0, 17, 0) {
-            let content = content
-                .replace("cuda = [\"burn/cuda\"]", "cuda = [\"burn/cuda-jit\"]")
-                .replace("rocm = [\"burn/rocm\"]", "rocm = [\"burn/hip-jit\"]")
-                .replace(
-                    "ndarray-simd = [\"ndarray\", \"burn/simd\"]",
-                    "ndarray-simd = [\"ndarray\"]",
-                )
-                .replace(
-                    "vulkan = [\"burn/vulkan\", \"burn/autotune\"]",
-                    "vulkan = [\"burn/wgpu-spirv\", \"burn/autotune\"]",
-                )
-                .replace(
-                    "metal = [\"burn/vulkan\", \"burn/autotune\"]",
-                    "metal = [\"burn/wgpu\", \"burn/autotune\"]",
-                )
-                .replace(
-                    "ndarray-simd = [\"burn/ndarray\", \"burn/simd\"]",
-                    "ndarray-simd = [\"burn/ndarray\"]",
-                )
-                .replace(
-                    "candle-metal = [\"burn/candle\", \"burn/candle-metal\"]",
-                    "candle-metal = [\"burn/candle\", \"burn/metal\"]",
-                )
+    /// Generate the feature flag of every registered backend for the given Burn version, the
+    /// features missing from the crate are added.
+    fn update_feature_flags(version: Option<&Version>, content: String) -> String {
+        let mut content = content;
+        for backend in BACKENDS {
+            let definition = backend.feature_definition(version);
+            let feature_re = Regex::new(&format!(
+                r"(?m)^{} = \[[^\]]*\]",
+                regex::escape(backend.name)
+            ))
+            .unwrap();
+
+            content = if feature_re.is_match(&content) {
+                feature_re
+                    .replace_all(&content, regex::NoExpand(&definition))
+                    .to_string()
+            } else if content.contains("[features]") {
+                content.replacen("[features]", &format!("[features]\n{definition}"), 1)
+            } else {
+                content
+            };
+        }
+
+        match version {
+            Some(version) if version < &Version::new(0, 17, 0) => {
                 // Use matching `rand` version (binary and data benchmarks)
-                .replace(
+                let content = content.replace(
                     "rand = { version = \"0.9.0\" }",
                     "rand = { version = \"0.8.5\" }",
                 );
 
-            if (version < &Version::new(0, 16, 1)) & !content.contains("bincode = \"=2.0.0-rc.3\"")
-            {
-                content.replace(
-                    "[dependencies]",
-                    "[dependencies]\nbincode = \"=2.0.0-rc.3\"\nbincode_derive = \"=2.0.0-rc.3\"",
-                )
-            } else {
-                content
+                if (version < &Version::new(0, 16, 1))
+                    & !content.contains("bincode = \"=2.0.0-rc.3\"")
+                {
+                    content.replace(
+                        "[dependencies]",
+                        "[dependencies]\nbincode = \"=2.0.0-rc.3\"\nbincode_derive = \"=2.0.0-rc.3\"",
+                    )
+                } else {
+                    content
+                }
             }
-        } else {
-            content
+            _ => content,
         }
     }
+
     fn update_burn_version(
         &self,
         content: &DependencyContent,
@@ -244,16 +303,14 @@ impl Dependency {
         match &content.workspace {
             Some(original) => {
                 let workspace = update_version(&original);
-                let benches = Self::update_feature_flags(version, content.benches.clone());
 
                 Ok(DependencyContentUpdate {
-                    benches: Some(benches),
+                    benches: None,
                     workspace: Some(workspace),
                 })
             }
             None => {
                 let benches = update_version(&content.benches);
-                let benches = Self::update_feature_flags(version, benches);
 
                 Ok(DependencyContentUpdate {
                     benches: Some(benches),
@@ -333,3 +390,52 @@ fn is_commit_hash(reference: &str) -> bool {
     let re = Regex::new(r"^[0-9a-f]{7,40}$").unwrap();
     re.is_match(reference)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feature_flags_are_generated_from_the_registry() {
+        let content = "[features]\ncuda = [\"burn/cuda\", \"burn/autotune\"]\nf32 = []\n";
+
+        let legacy =
+            Dependency::update_feature_flags(Some(&Version::new(0, 16, 0)), content.into());
+        assert!(legacy.contains("cuda = [\"burn/cuda-jit\", \"burn/autotune\"]\n"));
+        assert!(legacy.contains("wgpu = [\"burn/wgpu\", \"burn/autotune\"]\n"));
+        assert!(legacy.contains("f32 = []\n"));
+
+        let current = Dependency::update_feature_flags(None, content.into());
+        assert!(current.contains("cuda = [\"burn/cuda\", \"burn/autotune\"]\n"));
+        assert_eq!(current.matches("\ncuda = [").count(), 1);
+    }
+
+    #[test]
+    fn burn_commit_is_resolved_from_the_lockfile() {
+        let lockfile = r#"
+version = 4
+
+[[package]]
+name = "burn-core"
+version = "0.18.0"
+source = "git+https://github.com/tracel-ai/burn?branch=main#1111111111111111111111111111111111111111"
+
+[[package]]
+name = "burn"
+version = "0.18.0"
+source = "git+https://github.com/tracel-ai/burn?branch=main#02d37011ab4dc773286e5983c09cde61f95ba4b5"
+"#;
+        assert_eq!(
+            burn_commit_from_lockfile(lockfile).as_deref(),
+            Some("02d37011ab4dc773286e5983c09cde61f95ba4b5")
+        );
+
+        let crate_lockfile = r#"
+[[package]]
+name = "burn"
+version = "0.17.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+        assert_eq!(burn_commit_from_lockfile(crate_lockfile), None);
+    }
+}
@@ -1,9 +1,12 @@
 pub(crate) mod auth;
 mod base;
-mod dependency;
+mod bundle;
+pub(crate) mod dependency;
 mod processor;
 mod progressbar;
 mod reports;
+mod server;
+mod suite;
 mod workflow;
 
 pub use base::*;
@@ -1,4 +1,5 @@
 use super::progressbar::RunnerProgressBar;
+use crate::BenchmarkCase;
 use glob::glob;
 use std::collections::HashMap;
 use std::io::{self, BufRead, BufReader};
@@ -39,6 +40,29 @@ impl OutputProcessor for SinkProcessor {
     fn finish(&self) {}
 }
 
+/// A processor that collects the benchmark cases printed by benches executed in listing mode.
+#[derive(Default)]
+pub struct CaseCollector {
+    cases: Mutex<Vec<BenchmarkCase>>,
+}
+
+impl CaseCollector {
+    /// The cases collected so far.
+    pub fn cases(&self) -> Vec<BenchmarkCase> {
+        self.cases.lock().unwrap().clone()
+    }
+}
+
+impl OutputProcessor for CaseCollector {
+    fn process_line(&self, line: &str) {
+        if let Some(case) = BenchmarkCase::parse(line) {
+            self.cases.lock().unwrap().push(case);
+        }
+    }
+    fn progress(&self) {}
+    fn finish(&self) {}
+}
+
 /// A processor for a nice and compact output experience using a progress bar
 pub struct NiceProcessor {
     bench: String,
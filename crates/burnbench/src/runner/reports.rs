@@ -1,69 +1,78 @@
 use comfy_table::{Cell, CellAlignment, Color, Table};
 use core::fmt;
 use std::{
+    collections::BTreeMap,
     fmt::Display,
     fs,
-    io::{BufRead, BufReader},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
-use crate::persistence::BenchmarkRecord;
+use crate::DeviceSelector;
+use crate::persistence::{BenchmarkRecord, migrate_record};
+use crate::storage::{Run, RunMetadata};
 
 pub(crate) struct FailedBenchmark {
+    pub(crate) suite: PathBuf,
     pub(crate) bench: String,
     pub(crate) backend: String,
+    pub(crate) device: DeviceSelector,
+    pub(crate) threads: Option<usize>,
 }
 
 impl fmt::Display for FailedBenchmark {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Run the benchmark with verbose enabled to see the error:\ncargo run --bin burnbench -- run --benches {} --backends {} --verbose",
-            self.bench, self.backend
+            "Run the benchmark with verbose enabled to see the error:\ncargo run --bin burnbench -- run --suite {} --benches {} --backends {} --device {}{} --verbose",
+            self.suite.display(),
+            self.bench,
+            self.backend,
+            self.device,
+            self.threads
+                .map(|threads| format!(" --threads {threads}"))
+                .unwrap_or_default()
         )
     }
 }
 
 pub(crate) struct BenchmarkCollection {
     failed_benchmarks: Vec<FailedBenchmark>,
-    results_file: PathBuf,
+    run: Run,
     successful_records: Vec<BenchmarkRecord>,
 }
 
-impl Default for BenchmarkCollection {
-    fn default() -> Self {
-        let results_file = dirs::home_dir()
-            .expect("Home directory should exist")
-            .join(".cache")
-            .join("burn")
-            .join("burnbench")
-            .join("benchmark_results.txt");
-        fs::remove_file(results_file.clone()).ok();
+impl BenchmarkCollection {
+    /// Collect the results of the given run.
+    pub(crate) fn new(run: Run) -> Self {
         Self {
             failed_benchmarks: vec![],
-            results_file,
+            run,
             successful_records: vec![],
         }
     }
-}
 
-impl BenchmarkCollection {
     pub(crate) fn push_failed_benchmark(&mut self, benchmark: FailedBenchmark) {
         self.failed_benchmarks.push(benchmark);
     }
 
+    /// Metadata of the run of the collection.
+    pub(crate) fn run(&self) -> &RunMetadata {
+        &self.run.metadata
+    }
+
+    /// The records of the benchmarks that succeeded, loaded with [load_records](Self::load_records).
+    pub(crate) fn records(&self) -> &[BenchmarkRecord] {
+        &self.successful_records
+    }
+
+    /// Load the records saved in the run, the files that cannot be read are skipped with a
+    /// warning.
     pub(crate) fn load_records(&mut self) -> &mut Self {
-        if let Ok(file) = fs::File::open(self.results_file.clone()) {
-            let file_reader = BufReader::new(file);
-            for file in file_reader.lines() {
-                let file_path = file.unwrap();
-                if let Ok(br_file) = fs::File::open(file_path.clone()) {
-                    let benchmarkrecord =
-                        serde_json::from_reader::<_, BenchmarkRecord>(br_file).unwrap();
-                    self.successful_records.push(benchmarkrecord)
-                } else {
-                    println!("Cannot find the benchmark-record file: {}", file_path);
-                };
+        for file_path in self.run.record_files() {
+            match read_record(&file_path) {
+                Ok(record) => self.successful_records.push(record),
+                Err(err) => eprintln!("⚠️ Skipping record {} ({err})", file_path.display()),
             }
         }
 
@@ -73,11 +82,13 @@ impl BenchmarkCollection {
     pub(crate) fn get_ascii_table(&self) -> String {
         let mut records = self.successful_records.clone();
 
-        // Sort by benchmark name, then shapes, then median
+        // Sort by run, then benchmark name, then shapes, then median
         records.sort_by(|a, b| {
-            a.results
-                .name
-                .cmp(&b.results.name)
+            a.run
+                .started_at
+                .cmp(&b.run.started_at)
+                .then_with(|| a.run.id.cmp(&b.run.id))
+                .then_with(|| a.results.name.cmp(&b.results.name))
                 .then_with(|| a.results.shapes.cmp(&b.results.shapes))
                 .then_with(|| {
                     a.results
@@ -88,66 +99,331 @@ impl BenchmarkCollection {
                 })
         });
 
+        // The metrics column is only displayed when a benchmark reports custom metrics
+        let with_metrics = records
+            .iter()
+            .any(|record| !record.results.metrics.is_empty());
+
+        // The run column is only displayed when the records come from several runs
+        let with_run = records
+            .iter()
+            .any(|record| record.run.id != records[0].run.id);
+        // The overhead column is only displayed when a benchmark was measured with dual timing
+        let with_overhead = records
+            .iter()
+            .any(|record| !record.results.raw.device_durations.is_empty());
+
         let mut table = Table::new();
         table.load_preset(comfy_table::presets::ASCII_MARKDOWN);
-        table.set_header(vec![
+        let mut header = vec![];
+        if with_run {
+            header.push("Run");
+        }
+        header.extend([
             "Benchmark",
             "Burn Version",
             "Shapes",
             "Feature",
             "Backend",
             "Device",
+            "Timing",
             "Median",
+            "Peak Memory",
+            "Allocations",
         ]);
+        if with_overhead {
+            header.push("Overhead");
+        }
+        if with_metrics {
+            header.push("Metrics");
+        }
+        let separator = vec![Cell::new("----").fg(Color::DarkGrey); header.len()];
+        table.set_header(header);
 
+        let mut prev_run = "";
         let mut prev_benchmark = "";
         let mut prev_shapes = vec![];
 
         // success benchmarks
         for record in &records {
-            if prev_benchmark != record.results.name || prev_shapes != record.results.shapes {
+            if prev_run != record.run.id
+                || prev_benchmark != record.results.name
+                || prev_shapes != record.results.shapes
+            {
                 if !prev_benchmark.is_empty() {
-                    table.add_row(vec![
-                        Cell::new("----").fg(Color::DarkGrey),
-                        Cell::new("----").fg(Color::DarkGrey),
-                        Cell::new("----").fg(Color::DarkGrey),
-                        Cell::new("----").fg(Color::DarkGrey),
-                        Cell::new("----").fg(Color::DarkGrey),
-                        Cell::new("----").fg(Color::DarkGrey),
-                        Cell::new("----").fg(Color::DarkGrey),
-                    ]);
+                    table.add_row(separator.clone());
                 }
+                prev_run = &record.run.id;
                 prev_benchmark = &record.results.name;
                 prev_shapes = record.results.shapes.clone();
             }
 
-            table.add_row(vec![
+            let mut row = vec![];
+            if with_run {
+                row.push(Cell::new(RunFmt::new(&record.run)).fg(Color::Green));
+            }
+            row.extend([
                 Cell::new(&record.results.name).fg(Color::Green),
-                Cell::new(&record.burn_version).fg(Color::Green),
+                Cell::new(VersionFmt::new(
+                    &record.burn_version,
+                    record.burn_commit.as_deref(),
+                ))
+                .fg(Color::Green),
                 Cell::new(format!("{}", ShapeFmt::new(&record.results.shapes))).fg(Color::Green),
                 Cell::new(&record.feature).fg(Color::Green),
                 Cell::new(format!("`{}`", &record.backend)).fg(Color::Green),
-                Cell::new(&record.device).fg(Color::Green),
+                Cell::new(DeviceFmt::new(&record.device, record.execution.threads))
+                    .fg(Color::Green),
+                Cell::new(record.results.raw.timing_method).fg(Color::Green),
                 Cell::new(format!("{:.3?}", record.results.computed.median))
                     .set_alignment(CellAlignment::Right),
+                Cell::new(BytesFmt::new(record.results.memory.peak_resident_bytes))
+                    .set_alignment(CellAlignment::Right),
+                Cell::new(
+                    record
+                        .results
+                        .memory
+                        .allocations_per_sample
+                        .map(|allocations| allocations.to_string())
+                        .unwrap_or("-".to_string()),
+                )
+                .set_alignment(CellAlignment::Right),
             ]);
+            if with_overhead {
+                let overhead = record
+                    .results
+                    .raw
+                    .device_overhead()
+                    .map(|overhead| format!("{overhead:.2}x"))
+                    .unwrap_or("-".to_string());
+                row.push(Cell::new(overhead).set_alignment(CellAlignment::Right));
+            }
+            if with_metrics {
+                let metrics = record
+                    .results
+                    .metrics
+                    .iter()
+                    .map(|metric| format!("{}: {:.3}", metric.name, metric.value))
+                    .collect::<Vec<_>>();
+                row.push(Cell::new(metrics.join(", ")));
+            }
+            table.add_row(row);
         }
 
         // failed benchmarks
         for benchmark in &self.failed_benchmarks {
-            table.add_row(vec![
+            let mut row = vec![];
+            if with_run {
+                row.push(Cell::new(RunFmt::new(&self.run.metadata)).fg(Color::Red));
+            }
+            row.extend([
                 Cell::new(&benchmark.bench).fg(Color::Red),
                 Cell::new("-"),
                 Cell::new("-"),
                 Cell::new("-"),
                 Cell::new(format!("`{}`", &benchmark.backend)).fg(Color::Red),
+                Cell::new(DeviceFmt::new(&benchmark.device, benchmark.threads)).fg(Color::Red),
                 Cell::new("-"),
                 Cell::new("FAILED").fg(Color::Red),
+                Cell::new("-"),
+                Cell::new("-"),
             ]);
+            if with_overhead {
+                row.push(Cell::new("-"));
+            }
+            if with_metrics {
+                row.push(Cell::new("-"));
+            }
+            table.add_row(row);
         }
 
         table.to_string()
     }
+
+    /// Table of the speedup and parallel efficiency of the benchmarks executed with several
+    /// thread counts, `None` without a thread sweep.
+    ///
+    /// Each thread count is compared to the smallest thread count of the same benchmark.
+    pub(crate) fn get_scaling_table(&self) -> Option<String> {
+        let mut sweeps: BTreeMap<_, Vec<(usize, Duration)>> = BTreeMap::new();
+        for record in &self.successful_records {
+            let Some(threads) = record.execution.threads else {
+                continue;
+            };
+            let key = (
+                record.results.name.as_str(),
+                &record.results.shapes,
+                record.burn_version.as_str(),
+                record.feature.as_str(),
+                record.backend.as_str(),
+                record.device.as_str(),
+            );
+            sweeps
+                .entry(key)
+                .or_default()
+                .push((threads, record.results.computed.median));
+        }
+        sweeps.retain(|_, runs| runs.len() > 1);
+
+        if sweeps.is_empty() {
+            return None;
+        }
+
+        let mut table = Table::new();
+        table.load_preset(comfy_table::presets::ASCII_MARKDOWN);
+        table.set_header(vec![
+            "Benchmark",
+            "Burn Version",
+            "Shapes",
+            "Feature",
+            "Backend",
+            "Threads",
+            "Median",
+            "Speedup",
+            "Efficiency",
+        ]);
+
+        for ((name, shapes, burn_version, feature, backend, _), mut runs) in sweeps {
+            runs.sort();
+            let base = runs[0];
+            for run in runs {
+                let (speedup, efficiency) = scaling_efficiency(base, run);
+                table.add_row(vec![
+                    Cell::new(name).fg(Color::Green),
+                    Cell::new(burn_version).fg(Color::Green),
+                    Cell::new(format!("{}", ShapeFmt::new(shapes))).fg(Color::Green),
+                    Cell::new(feature).fg(Color::Green),
+                    Cell::new(format!("`{backend}`")).fg(Color::Green),
+                    Cell::new(run.0).set_alignment(CellAlignment::Right),
+                    Cell::new(format!("{:.3?}", run.1)).set_alignment(CellAlignment::Right),
+                    Cell::new(format!("{speedup:.2}x")).set_alignment(CellAlignment::Right),
+                    Cell::new(format!("{:.0}%", efficiency * 100.0))
+                        .set_alignment(CellAlignment::Right),
+                ]);
+            }
+        }
+
+        Some(table.to_string())
+    }
+}
+
+/// Read a record file, records written by older versions are upgraded to the current schema.
+fn read_record(path: &Path) -> Result<BenchmarkRecord, String> {
+    let content = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let value = serde_json::from_str(&content).map_err(|err| err.to_string())?;
+    serde_json::from_value(migrate_record(value)?).map_err(|err| err.to_string())
+}
+
+/// Speedup and parallel efficiency of a run compared to the base run, both given as a thread
+/// count and a median duration.
+fn scaling_efficiency(base: (usize, Duration), run: (usize, Duration)) -> (f64, f64) {
+    let speedup = base.1.as_secs_f64() / run.1.as_secs_f64();
+    let efficiency = speedup * base.0 as f64 / run.0 as f64;
+    (speedup, efficiency)
+}
+
+/// Format a requested burn version with the commit resolved by the build, if any.
+struct VersionFmt<'a> {
+    version: &'a str,
+    commit: Option<&'a str>,
+}
+
+impl<'a> VersionFmt<'a> {
+    fn new(version: &'a str, commit: Option<&'a str>) -> Self {
+        Self { version, commit }
+    }
+}
+
+impl Display for VersionFmt<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.commit {
+            // The version is already the requested commit
+            Some(commit) if !commit.starts_with(self.version) => {
+                write!(
+                    f,
+                    "{} ({})",
+                    self.version,
+                    commit.get(..7).unwrap_or(commit)
+                )
+            }
+            _ => f.write_str(self.version),
+        }
+    }
+}
+
+/// Format a run with the beginning of its identifier and its note.
+struct RunFmt<'a> {
+    run: &'a RunMetadata,
+}
+
+impl<'a> RunFmt<'a> {
+    fn new(run: &'a RunMetadata) -> Self {
+        Self { run }
+    }
+}
+
+impl Display for RunFmt<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = self.run.id.get(..8).unwrap_or(&self.run.id);
+        match &self.run.note {
+            Some(note) => write!(f, "{id} ({note})"),
+            None => f.write_str(id),
+        }
+    }
+}
+
+/// Format an amount of bytes with a binary unit, `-` if unknown.
+struct BytesFmt {
+    bytes: Option<u64>,
+}
+
+impl BytesFmt {
+    fn new(bytes: Option<u64>) -> Self {
+        Self { bytes }
+    }
+}
+
+impl Display for BytesFmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+        let Some(bytes) = self.bytes else {
+            return f.write_str("-");
+        };
+        let mut value = bytes as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+
+        if unit == 0 {
+            write!(f, "{bytes} B")
+        } else {
+            write!(f, "{value:.1} {}", UNITS[unit])
+        }
+    }
+}
+
+/// Format a device with the thread count it was executed with, if any.
+struct DeviceFmt<'a> {
+    device: &'a dyn Display,
+    threads: Option<usize>,
+}
+
+impl<'a> DeviceFmt<'a> {
+    fn new(device: &'a dyn Display, threads: Option<usize>) -> Self {
+        Self { device, threads }
+    }
+}
+
+impl Display for DeviceFmt<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.threads {
+            Some(threads) => write!(f, "{} ({threads} threads)", self.device),
+            None => write!(f, "{}", self.device),
+        }
+    }
 }
 
 pub struct ShapeFmt<'a> {
@@ -189,3 +465,79 @@ impl Display for ShapeFmt<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaling_efficiency_is_relative_to_the_base_run() {
+        let base = (1, Duration::from_millis(80));
+
+        assert_eq!(scaling_efficiency(base, base), (1.0, 1.0));
+        assert_eq!(
+            scaling_efficiency(base, (4, Duration::from_millis(40))),
+            (2.0, 0.5)
+        );
+        assert_eq!(
+            scaling_efficiency(
+                (2, Duration::from_millis(80)),
+                (4, Duration::from_millis(40))
+            ),
+            (2.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn invalid_record_files_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let valid = dir.path().join("bench_valid.json");
+        let truncated = dir.path().join("bench_truncated.json");
+        fs::write(
+            &valid,
+            serde_json::to_string(&BenchmarkRecord::default()).unwrap(),
+        )
+        .unwrap();
+        fs::write(&truncated, "{\"backend\": ").unwrap();
+
+        assert!(read_record(&valid).is_ok());
+        assert!(read_record(&truncated).is_err());
+        assert!(read_record(&dir.path().join("bench_missing.json")).is_err());
+    }
+
+    #[test]
+    fn bytes_are_formatted_with_binary_units() {
+        assert_eq!(BytesFmt::new(None).to_string(), "-");
+        assert_eq!(BytesFmt::new(Some(512)).to_string(), "512 B");
+        assert_eq!(
+            BytesFmt::new(Some(3 * 1024 * 1024 / 2)).to_string(),
+            "1.5 MiB"
+        );
+    }
+
+    #[test]
+    fn runs_are_formatted_with_their_note() {
+        let mut run = RunMetadata {
+            id: "67e55044-10b1-426f-9247-bb680e5fe0c8".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(RunFmt::new(&run).to_string(), "67e55044");
+
+        run.note = Some("new allocator".to_string());
+        assert_eq!(RunFmt::new(&run).to_string(), "67e55044 (new allocator)");
+    }
+
+    #[test]
+    fn versions_are_formatted_with_their_commit() {
+        let commit = "02d37011ab4dc773286e5983c09cde61f95ba4b5";
+        assert_eq!(
+            VersionFmt::new("main", Some(commit)).to_string(),
+            "main (02d3701)"
+        );
+        assert_eq!(
+            VersionFmt::new("02d3701", Some(commit)).to_string(),
+            "02d3701"
+        );
+        assert_eq!(VersionFmt::new("0.17.0", None).to_string(), "0.17.0");
+    }
+}
@@ -0,0 +1,621 @@
+use hmac_sha256::HMAC;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::bundle::{Bundle, saved_records};
+use crate::storage::{Run, RunMetadata};
+use crate::{BenchmarkRecord, data_dir, migrate_record, parse_tag};
+
+/// Environment variable holding the tokens accepted by the server, as comma separated
+/// `name=token` pairs.
+pub const SERVER_TOKENS_ENV_VAR: &str = "BURN_BENCH_SERVER_TOKENS";
+/// Environment variable holding the secret signing the webhooks, shared with the runner.
+const WEBHOOK_SECRET_ENV_VAR: &str = "WEBHOOK_PAYLOAD_SECRET";
+
+/// Prefix of the routes of the benchmark server, the website is served at the root.
+const API_PREFIX: &str = "/v1/";
+/// Name of the directory holding the received webhooks in the data directory.
+const WEBHOOKS_DIR: &str = "webhooks";
+/// Largest request accepted by the server.
+const MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+/// Self-hosted benchmark server, storing the shared results in the data directory.
+///
+/// Each connection is handled on its own thread, the records are stored one request at a time.
+pub(crate) struct Server {
+    listener: TcpListener,
+    /// The users, by token.
+    users: BTreeMap<String, String>,
+    webhook_secret: Option<String>,
+    /// Identities of the records of the data directory, read once when the server starts and held
+    /// while storing the received records to skip the duplicates.
+    records: Mutex<HashSet<String>>,
+}
+
+/// Parse a token accepted by the server, given as `name=token`.
+///
+/// The runner only sends tokens in the format of GitHub, starting with `ghu_` or `github_pat_`.
+pub(crate) fn parse_server_token(token: &str) -> Result<(String, String), String> {
+    let (name, token) = parse_tag(token)?;
+    if !token.starts_with("ghu_") && !token.starts_with("github_pat_") {
+        return Err(format!(
+            "invalid token for '{name}', it must start with 'ghu_' or 'github_pat_'"
+        ));
+    }
+
+    Ok((name, token))
+}
+
+impl Server {
+    /// Listen on the given address, the users are given as `(name, token)` pairs.
+    pub(crate) fn bind(address: &str, users: Vec<(String, String)>) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(address)?,
+            users: users
+                .into_iter()
+                .map(|(name, token)| (token, name))
+                .collect(),
+            webhook_secret: std::env::var(WEBHOOK_SECRET_ENV_VAR).ok(),
+            records: Mutex::new(saved_records()),
+        })
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Handle the requests until the process is stopped.
+    pub(crate) fn serve(&self) {
+        std::thread::scope(|scope| {
+            for stream in self.listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                scope.spawn(move || {
+                    stream.set_read_timeout(Some(Duration::from_secs(30))).ok();
+                    let response = match Request::read(&mut stream) {
+                        Ok(request) => self.handle(&request),
+                        Err(response) => response,
+                    };
+                    response.write(&mut stream).ok();
+                });
+            }
+        });
+    }
+
+    fn handle(&self, request: &Request) -> Response {
+        match request.path.strip_prefix(API_PREFIX) {
+            Some(route) => self.handle_api(route, request),
+            None => self.handle_website(request),
+        }
+    }
+
+    fn handle_api(&self, route: &str, request: &Request) -> Response {
+        match (request.method.as_str(), route) {
+            ("POST", "benchmarks") => match self.user(request, &["Bearer", "token"]) {
+                Some(user) => {
+                    let mut known = self.records.lock().unwrap_or_else(|err| err.into_inner());
+                    receive_records(user, &request.body, &mut known)
+                }
+                None => Response::error(401, "invalid or missing token"),
+            },
+            ("GET", "users/me") => match self.user(request, &["Bearer", "token"]) {
+                Some(user) => Response::json(200, json!({ "nickname": user })),
+                None => Response::error(401, "invalid or missing token"),
+            },
+            // The tokens of the server do not expire, the refresh token is the access token
+            ("POST", "auth/refresh-token") => match self.user(request, &["Bearer-Refresh"]) {
+                Some(_) => {
+                    let token = request.token(&["Bearer-Refresh"]).unwrap_or_default();
+                    Response::json(
+                        200,
+                        json!({ "access_token": token, "refresh_token": token }),
+                    )
+                }
+                None => Response::error(401, "invalid or missing refresh token"),
+            },
+            ("POST", "burn_bench/webhook/benchmark") => self.receive_webhook(request),
+            _ => Response::error(404, "not found"),
+        }
+    }
+
+    fn handle_website(&self, request: &Request) -> Response {
+        if request.method != "GET" {
+            return Response::error(405, "method not allowed");
+        }
+
+        match request.path.as_str() {
+            "/" | "/benchmarks/community-benchmarks" => {
+                Response::html(200, runs_page(request.query.get("user")))
+            }
+            path => match path
+                .strip_prefix("/runs/")
+                .and_then(|name| Run::list().into_iter().find(|run| run.name() == name))
+            {
+                Some(run) => Response::html(200, run_page(&run)),
+                None => Response::error(404, "not found"),
+            },
+        }
+    }
+
+    /// The user authenticated by the token of the request.
+    fn user(&self, request: &Request, schemes: &[&str]) -> Option<&str> {
+        let token = request.token(schemes)?;
+        self.users.get(token).map(String::as_str)
+    }
+
+    fn receive_webhook(&self, request: &Request) -> Response {
+        let Some(secret) = &self.webhook_secret else {
+            return Response::error(
+                503,
+                "the webhooks are disabled, WEBHOOK_PAYLOAD_SECRET is unset",
+            );
+        };
+        let signature = format!(
+            "sha256={}",
+            hex::encode(HMAC::mac(&request.body, secret.as_bytes()))
+        );
+        if request.headers.get("x-hub-signature-256") != Some(&signature) {
+            return Response::error(401, "invalid signature");
+        }
+        let Ok(event) = serde_json::from_slice::<Value>(&request.body) else {
+            return Response::error(400, "invalid JSON");
+        };
+
+        let delivery = request
+            .headers
+            .get("x-github-delivery")
+            .filter(|id| uuid::Uuid::parse_str(id).is_ok())
+            .cloned()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let dir = data_dir().join(WEBHOOKS_DIR);
+        let saved = fs::create_dir_all(&dir).and_then(|_| {
+            fs::write(
+                dir.join(format!("{delivery}.json")),
+                serde_json::to_string_pretty(&event).unwrap(),
+            )
+        });
+
+        match saved {
+            Ok(()) => Response::json(202, json!({ "delivery": delivery })),
+            Err(err) => Response::error(500, &format!("cannot save the webhook: {err}")),
+        }
+    }
+}
+
+/// Save the records posted by a user in the runs of the data directory.
+///
+/// The body is a record or an array of records, which are grouped by the run saved in each
+/// record. The records whose identity is known are skipped.
+fn receive_records(user: &str, body: &[u8], known: &mut HashSet<String>) -> Response {
+    let records = match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Array(records)) => records,
+        Ok(record @ Value::Object(_)) => vec![record],
+        _ => return Response::error(400, "expected a record or an array of records"),
+    };
+
+    // Records without a run, saved by older versions, are saved in a new run
+    let unknown_run = RunMetadata {
+        id: uuid::Uuid::new_v4().to_string(),
+        started_at: chrono::Utc::now().timestamp_millis() as u128,
+        ..Default::default()
+    };
+    let mut runs = BTreeMap::<String, (RunMetadata, Vec<Value>)>::new();
+    for record in records {
+        let mut metadata = serde_json::from_value::<RunMetadata>(record["run"].clone())
+            .ok()
+            .filter(|metadata| !metadata.id.is_empty())
+            .unwrap_or_else(|| unknown_run.clone());
+        metadata.tags.insert("user".to_string(), user.to_string());
+        runs.entry(metadata.id.clone())
+            .or_insert_with(|| (metadata, vec![]))
+            .1
+            .push(record);
+    }
+
+    let (mut saved, mut duplicates) = (0, 0);
+    for (metadata, records) in runs.into_values() {
+        match Bundle::from_records(metadata, records).import_with(known) {
+            Ok(summary) => {
+                saved += summary.records;
+                duplicates += summary.duplicates;
+            }
+            Err(err) => return Response::error(500, &format!("cannot save the records: {err}")),
+        }
+    }
+    println!("📥 Received {saved} records from {user}, skipped {duplicates} duplicates");
+
+    Response::json(201, json!({ "records": saved, "duplicates": duplicates }))
+}
+
+/// Page listing the runs, optionally only those shared by the given user.
+fn runs_page(user: Option<&String>) -> String {
+    let mut rows = String::new();
+    for run in Run::list().iter().rev() {
+        let metadata = &run.metadata;
+        if user.is_some_and(|user| metadata.tags.get("user") != Some(user)) {
+            continue;
+        }
+        let tags = metadata
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        rows.push_str(&format!(
+            "<tr><td><a href=\"/runs/{name}\">{name}</a></td><td>{started}</td><td>{tags}</td><td>{note}</td><td>{records}</td></tr>\n",
+            name = escape_html(&run.name()),
+            started = format_time(metadata.started_at),
+            tags = escape_html(&tags),
+            note = escape_html(metadata.note.as_deref().unwrap_or_default()),
+            records = run.record_files().len(),
+        ));
+    }
+
+    let title = match user {
+        Some(user) => format!("Runs shared by {}", escape_html(user)),
+        None => "Runs".to_string(),
+    };
+    page(
+        &title,
+        &format!(
+            "<table>\n<tr><th>Run</th><th>Started</th><th>Tags</th><th>Note</th><th>Records</th></tr>\n{rows}</table>"
+        ),
+    )
+}
+
+/// Page listing the records of a run.
+fn run_page(run: &Run) -> String {
+    let mut records = run
+        .record_files()
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|content| serde_json::from_str::<Value>(&content).ok())
+        .filter_map(|record| migrate_record(record).ok())
+        .filter_map(|record| serde_json::from_value::<BenchmarkRecord>(record).ok())
+        .collect::<Vec<_>>();
+    records.sort_by(|a, b| a.results.name.cmp(&b.results.name));
+
+    let mut rows = String::new();
+    for record in records {
+        let shapes = record
+            .results
+            .shapes
+            .iter()
+            .map(|shape| format!("{shape:?}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.2?}</td><td>{}</td></tr>\n",
+            escape_html(&record.results.name),
+            escape_html(&shapes),
+            escape_html(&record.backend),
+            escape_html(&record.device),
+            escape_html(&record.feature),
+            escape_html(&record.burn_version),
+            record.results.computed.median,
+            record.results.raw.durations.len(),
+        ));
+    }
+
+    let metadata = &run.metadata;
+    let note = metadata
+        .note
+        .as_deref()
+        .map(|note| format!("<p>{}</p>\n", escape_html(note)))
+        .unwrap_or_default();
+    page(
+        &format!("Run {}", escape_html(&run.name())),
+        &format!(
+            "<p><a href=\"/\">All runs</a> · started {} · {}</p>\n{note}<table>\n<tr><th>Benchmark</th><th>Shapes</th><th>Backend</th><th>Device</th><th>Feature</th><th>Version</th><th>Median</th><th>Samples</th></tr>\n{rows}</table>",
+            format_time(metadata.started_at),
+            escape_html(&metadata.id),
+        ),
+    )
+}
+
+fn page(title: &str, content: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title} - Burnbench</title>\n\
+         <style>body {{ font-family: sans-serif; margin: 2em; }} table {{ border-collapse: collapse; }} \
+         th, td {{ border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }}</style>\n\
+         </head>\n<body>\n<h1>{title}</h1>\n{content}\n</body>\n</html>\n"
+    )
+}
+
+fn format_time(timestamp: u128) -> String {
+    chrono::DateTime::from_timestamp_millis(timestamp as i64)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// An HTTP request, whose body is sized by its `Content-Length` header.
+struct Request {
+    method: String,
+    path: String,
+    query: BTreeMap<String, String>,
+    /// The headers, by lowercase name.
+    headers: BTreeMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn read(stream: &mut TcpStream) -> Result<Self, Response> {
+        let bad_request = || Response::error(400, "bad request");
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(|_| bad_request())?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(bad_request());
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| {
+                let value = percent_encoding::percent_decode_str(value).decode_utf8_lossy();
+                (key.to_string(), value.to_string())
+            })
+            .collect();
+
+        let mut headers = BTreeMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).map_err(|_| bad_request())?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').ok_or_else(bad_request)?;
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+
+        if headers.contains_key("transfer-encoding") {
+            return Err(Response::error(411, "length required"));
+        }
+        let length = match headers.get("content-length") {
+            Some(length) => length.parse::<usize>().map_err(|_| bad_request())?,
+            None => 0,
+        };
+        if length > MAX_BODY_SIZE {
+            return Err(Response::error(413, "payload too large"));
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).map_err(|_| bad_request())?;
+
+        Ok(Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            query,
+            headers,
+            body,
+        })
+    }
+
+    /// The token of the `Authorization` header given with one of the schemes.
+    fn token(&self, schemes: &[&str]) -> Option<&str> {
+        let (scheme, token) = self.headers.get("authorization")?.split_once(' ')?;
+        schemes.contains(&scheme).then(|| token.trim())
+    }
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    fn html(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "text/html; charset=utf-8",
+            body,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, json!({ "error": message }))
+    }
+
+    fn write(&self, stream: &mut TcpStream) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )?;
+        stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DATA_DIR_ENV_VAR;
+    use reqwest::blocking::Client;
+    use serial_test::serial;
+
+    const TOKEN: &str = "ghu_team_token";
+
+    fn start() -> String {
+        let server =
+            Server::bind("127.0.0.1:0", vec![("team".to_string(), TOKEN.to_string())]).unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap());
+        std::thread::spawn(move || server.serve());
+        url
+    }
+
+    #[test]
+    fn server_tokens_use_the_github_format() {
+        assert_eq!(
+            parse_server_token("team=github_pat_123").unwrap(),
+            ("team".to_string(), "github_pat_123".to_string())
+        );
+        assert!(parse_server_token("team=secret").is_err());
+    }
+
+    #[test]
+    fn users_are_authenticated_by_their_token() {
+        let url = start();
+        let client = Client::new();
+
+        let response = client
+            .get(format!("{url}/v1/users/me"))
+            .bearer_auth(TOKEN)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.json::<Value>().unwrap()["nickname"], "team");
+
+        let response = client
+            .get(format!("{url}/v1/users/me"))
+            .bearer_auth("ghu_unknown")
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 401);
+
+        let response = client
+            .post(format!("{url}/v1/auth/refresh-token"))
+            .header("Authorization", format!("Bearer-Refresh {TOKEN}"))
+            .body("")
+            .send()
+            .unwrap();
+        assert_eq!(response.json::<Value>().unwrap()["access_token"], TOKEN);
+    }
+
+    #[test]
+    fn idle_connections_do_not_block_the_requests() {
+        let url = start();
+        let _idle = TcpStream::connect(url.trim_start_matches("http://")).unwrap();
+
+        let response = Client::new()
+            .get(format!("{url}/v1/users/me"))
+            .bearer_auth(TOKEN)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    #[test]
+    #[serial]
+    fn shared_records_are_stored_and_browsable() {
+        let dir = tempfile::tempdir().unwrap();
+        unsafe {
+            std::env::set_var(DATA_DIR_ENV_VAR, dir.path());
+        }
+        let url = start();
+        let client = Client::new();
+        let mut record = BenchmarkRecord::default();
+        record.results.name = "matmul<f32>".to_string();
+        record.run.id = "0c3c4c7e-7c5b-4a4e-9d53-1c1a1d1e1f10".to_string();
+        record.run.started_at = 1710208069697;
+        let records = json!([record]);
+
+        let post = || {
+            client
+                .post(format!("{url}/v1/benchmarks"))
+                .bearer_auth(TOKEN)
+                .json(&records)
+                .send()
+                .unwrap()
+                .json::<Value>()
+                .unwrap()
+        };
+        assert_eq!(post(), json!({ "records": 1, "duplicates": 0 }));
+        assert_eq!(post(), json!({ "records": 0, "duplicates": 1 }));
+        // The records stored before the server started are known
+        let restarted = start();
+        let response = client
+            .post(format!("{restarted}/v1/benchmarks"))
+            .bearer_auth(TOKEN)
+            .json(&records)
+            .send()
+            .unwrap();
+        assert_eq!(
+            response.json::<Value>().unwrap(),
+            json!({ "records": 0, "duplicates": 1 })
+        );
+        let response = client
+            .post(format!("{url}/v1/benchmarks"))
+            .json(&records)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 401);
+
+        let runs = client.get(format!("{url}/?user=team")).send().unwrap();
+        assert!(runs.text().unwrap().contains("/runs/20240312-014749-697"));
+        let run = client
+            .get(format!("{url}/runs/20240312-014749-697"))
+            .send()
+            .unwrap();
+        assert!(run.text().unwrap().contains("matmul&lt;f32&gt;"));
+
+        // Another run started in the same millisecond is kept apart
+        record.results.name = "conv2d<f32>".to_string();
+        record.run.id = "5b0e2f1a-3d4c-4b6a-8e7f-9a0b1c2d3e4f".to_string();
+        let response = client
+            .post(format!("{url}/v1/benchmarks"))
+            .bearer_auth(TOKEN)
+            .json(&json!([record]))
+            .send()
+            .unwrap();
+        assert_eq!(
+            response.json::<Value>().unwrap(),
+            json!({ "records": 1, "duplicates": 0 })
+        );
+        let run = client
+            .get(format!("{url}/runs/20240312-014749-697"))
+            .send()
+            .unwrap()
+            .text()
+            .unwrap();
+        assert!(!run.contains("conv2d&lt;f32&gt;"));
+        let run = client
+            .get(format!("{url}/runs/20240312-014749-697-1"))
+            .send()
+            .unwrap();
+        assert!(run.text().unwrap().contains("conv2d&lt;f32&gt;"));
+
+        unsafe {
+            std::env::remove_var(DATA_DIR_ENV_VAR);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A crate containing benchmarks driven by burnbench.
+///
+/// Any crate can be used as a suite as long as its benches call
+/// [bench_on_backend](crate::bench_on_backend) and its `Cargo.toml` follows the contract described
+/// in the crate README.
+#[derive(Debug, Clone)]
+pub(crate) struct BenchSuite {
+    /// The name of the crate that contains the benchmarks.
+    pub(crate) name: String,
+    /// The directory of the crate.
+    pub(crate) path: PathBuf,
+}
+
+/// A bench declared in the crate Cargo.toml.
+#[derive(Debug)]
+pub(crate) struct BenchTarget {
+    pub(crate) name: String,
+    pub(crate) required_features: Vec<String>,
+}
+
+impl BenchSuite {
+    /// Load the suite of the crate located at the given path, either the crate directory or its
+    /// `Cargo.toml` file.
+    pub(crate) fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let path = if path.file_name().is_some_and(|name| name == "Cargo.toml") {
+            path.parent().unwrap_or(Path::new(".")).to_path_buf()
+        } else {
+            path.to_path_buf()
+        };
+
+        let manifest = Self::read_manifest(&path)?;
+        let name = manifest
+            .get("package")
+            .and_then(|package| package.get("name"))
+            .and_then(|name| name.as_str())
+            .ok_or_else(|| format!("{} is not a package", path.join("Cargo.toml").display()))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            path,
+        })
+    }
+
+    /// The path of the crate `Cargo.toml` file.
+    pub(crate) fn manifest_path(&self) -> PathBuf {
+        self.path.join("Cargo.toml")
+    }
+
+    /// The benches declared in the crate `Cargo.toml` file.
+    pub(crate) fn bench_targets(&self) -> Vec<BenchTarget> {
+        let manifest = Self::read_manifest(&self.path).expect("Failed to read Cargo.toml");
+
+        let Some(benches) = manifest.get("bench").and_then(|b| b.as_array()) else {
+            return vec![];
+        };
+
+        benches
+            .iter()
+            .filter_map(|bench| {
+                let name = bench.get("name").and_then(|v| v.as_str())?;
+                let required_features = bench
+                    .get("required-features")
+                    .and_then(|f| f.as_array())
+                    .map(|features| {
+                        features
+                            .iter()
+                            .filter_map(|v| v.as_str())
+                            .map(String::from)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                Some(BenchTarget {
+                    name: name.to_string(),
+                    required_features,
+                })
+            })
+            .collect()
+    }
+
+    /// The features required by the given bench.
+    pub(crate) fn required_features(&self, target_bench: &str) -> Vec<String> {
+        self.bench_targets()
+            .into_iter()
+            .find(|target| target.name == target_bench)
+            .map(|target| target.required_features)
+            .unwrap_or_default()
+    }
+
+    /// Returns true if the crate declares the given bench.
+    pub(crate) fn has_bench(&self, bench: &str) -> bool {
+        self.bench_targets()
+            .iter()
+            .any(|target| target.name == bench)
+    }
+
+    fn read_manifest(path: &Path) -> Result<toml::Value, String> {
+        let cargo_file_path = path.join("Cargo.toml");
+        let content = fs::read_to_string(&cargo_file_path)
+            .map_err(|err| format!("Failed to read {}: {err}", cargo_file_path.display()))?;
+        content
+            .parse()
+            .map_err(|err| format!("Invalid TOML in {}: {err}", cargo_file_path.display()))
+    }
+}
+
+/// Return the directory of the workspace containing the crate at the given path, the crate
+/// directory itself if it is not part of a workspace.
+pub(crate) fn workspace_root(crate_path: &Path) -> PathBuf {
+    let crate_path = fs::canonicalize(crate_path).unwrap_or_else(|_| crate_path.to_path_buf());
+    crate_path
+        .ancestors()
+        .find(|dir| {
+            fs::read_to_string(dir.join("Cargo.toml"))
+                .is_ok_and(|content| content.contains("[workspace]"))
+        })
+        .unwrap_or(&crate_path)
+        .to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suite_from_directory_or_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\n\n[package]\nname = \"my-benches\"\n\n[[bench]]\nname = \"model\"\nrequired-features = [\"onnx\"]\n",
+        )
+        .unwrap();
+
+        let from_dir = BenchSuite::from_path(dir.path()).unwrap();
+        let from_manifest = BenchSuite::from_path(dir.path().join("Cargo.toml")).unwrap();
+
+        assert_eq!(from_dir.name, "my-benches");
+        assert_eq!(from_manifest.path, dir.path());
+        assert!(from_dir.has_bench("model"));
+        assert!(!from_dir.has_bench("unary"));
+        assert_eq!(from_dir.required_features("model"), vec!["onnx"]);
+        assert_eq!(
+            workspace_root(&from_dir.path),
+            fs::canonicalize(dir.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn workspace_root_is_an_ancestor() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert_eq!(
+            workspace_root(root),
+            fs::canonicalize(root.join("../..")).unwrap()
+        );
+    }
+}
@@ -9,10 +9,10 @@ use std::{env, fs::File, io::BufReader};
 use hmac_sha256::HMAC;
 use uuid::Uuid;
 
-use crate::{TRACEL_CI_SERVER_BASE_URL, ci_errorln};
+use crate::{Endpoints, ci_errorln};
 
 fn get_webhook_url() -> String {
-    format!("{TRACEL_CI_SERVER_BASE_URL}burn_bench/webhook/benchmark")
+    Endpoints::current().server_url("burn_bench/webhook/benchmark")
 }
 
 pub(crate) fn send_started_event(inputs_file: &str) {
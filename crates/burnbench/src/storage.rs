@@ -0,0 +1,447 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Environment variable overriding the directory where burnbench stores its data.
+pub const DATA_DIR_ENV_VAR: &str = "BURNBENCH_HOME";
+/// Environment variable used by the runner to forward the directory of the current run to the
+/// benchmark binaries.
+pub const RUN_DIR_ENV_VAR: &str = "BURN_BENCH_RUN_DIR";
+
+/// Name of the directory containing the runs in the data directory.
+const RUNS_DIR: &str = "runs";
+/// Name of the directory holding the uploads that failed in the data directory.
+const UPLOADS_DIR: &str = "uploads";
+/// Name of the file holding the metadata of a run in its directory.
+const RUN_FILE: &str = "run.json";
+
+/// Data directory set with the `--data-dir` argument of the runner.
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Override the data directory of the current process.
+pub(crate) fn set_data_dir(path: PathBuf) {
+    DATA_DIR.set(path).ok();
+}
+
+/// Directory where the benchmark records and the authentication tokens are stored.
+///
+/// The directory is given by the `--data-dir` argument or the `BURNBENCH_HOME` environment
+/// variable, and defaults to `burn/burnbench` in the `XDG_CACHE_HOME` directory or in `~/.cache`.
+pub fn data_dir() -> PathBuf {
+    if let Some(path) = DATA_DIR.get() {
+        return path.clone();
+    }
+    if let Some(path) = std::env::var_os(DATA_DIR_ENV_VAR).filter(|path| !path.is_empty()) {
+        return PathBuf::from(path);
+    }
+
+    default_data_dir(
+        std::env::var_os("XDG_CACHE_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from),
+        dirs::home_dir(),
+    )
+}
+
+fn default_data_dir(xdg_cache_home: Option<PathBuf>, home_dir: Option<PathBuf>) -> PathBuf {
+    let cache_dir = xdg_cache_home.unwrap_or_else(|| {
+        home_dir
+            .expect("Home directory should exist")
+            .join(".cache")
+    });
+    cache_dir.join("burn").join("burnbench")
+}
+
+/// Metadata of a run, saved in the `run.json` file of its directory and in each of its records.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RunMetadata {
+    /// Unique identifier of the run.
+    pub id: String,
+    /// Time at which the run started, in milliseconds since the Unix epoch.
+    pub started_at: u128,
+    /// Tags given to the run with `--tag key=value`.
+    pub tags: BTreeMap<String, String>,
+    /// Note given to the run with `--note`.
+    pub note: Option<String>,
+}
+
+impl RunMetadata {
+    /// Name of the directory of the run, made of its start time.
+    pub(crate) fn dir_name(&self) -> String {
+        chrono::DateTime::from_timestamp_millis(self.started_at as i64)
+            .unwrap_or_default()
+            .format("%Y%m%d-%H%M%S-%3f")
+            .to_string()
+    }
+}
+
+/// A run of the benchmarks, whose records are saved in a directory of the data directory.
+#[derive(Debug, Clone)]
+pub(crate) struct Run {
+    pub(crate) path: PathBuf,
+    pub(crate) metadata: RunMetadata,
+}
+
+impl Run {
+    /// Create the directory of a new run.
+    pub(crate) fn create(tags: BTreeMap<String, String>, note: Option<String>) -> io::Result<Self> {
+        let now = chrono::Utc::now();
+        let metadata = RunMetadata {
+            id: uuid::Uuid::new_v4().to_string(),
+            started_at: now.timestamp_millis() as u128,
+            tags,
+            note,
+        };
+        let path = unique_path(&runs_dir(), &metadata.dir_name(), "");
+
+        Self::write(path, metadata)
+    }
+
+    /// Open the run of the given name, or create it with the given metadata if it does not exist.
+    ///
+    /// The name comes from imported bundles and shared results, it must be the name of a directory
+    /// of the runs directory. A directory of that name holding another run, started in the same
+    /// millisecond, is kept apart by adding a suffix to the name.
+    pub(crate) fn open_or_create(name: &str, metadata: RunMetadata) -> io::Result<Self> {
+        if !is_dir_name(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid run name '{name}'"),
+            ));
+        }
+
+        let mut path = runs_dir().join(name);
+        let mut suffix = 1;
+        while path.exists() {
+            let run = Self::open(&path)?;
+            if run.metadata.id == metadata.id {
+                return Ok(run);
+            }
+            path = runs_dir().join(format!("{name}-{suffix}"));
+            suffix += 1;
+        }
+
+        Self::write(path, metadata)
+    }
+
+    fn write(path: PathBuf, metadata: RunMetadata) -> io::Result<Self> {
+        fs::create_dir_all(&path)?;
+        fs::write(
+            path.join(RUN_FILE),
+            serde_json::to_string_pretty(&metadata).unwrap(),
+        )?;
+
+        Ok(Self { path, metadata })
+    }
+
+    /// Open the run saved in the given directory.
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let metadata = serde_json::from_str(&fs::read_to_string(path.join(RUN_FILE))?)
+            .map_err(io::Error::other)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            metadata,
+        })
+    }
+
+    /// The run created by the runner, or a new run when the benches are executed with cargo.
+    pub(crate) fn current() -> io::Result<Self> {
+        match std::env::var_os(RUN_DIR_ENV_VAR) {
+            Some(path) => Self::open(Path::new(&path)),
+            None => Self::create(BTreeMap::new(), None),
+        }
+    }
+
+    /// All the runs of the data directory, from the oldest to the most recent.
+    pub(crate) fn list() -> Vec<Self> {
+        let Ok(entries) = fs::read_dir(runs_dir()) else {
+            return vec![];
+        };
+
+        let mut runs = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| Self::open(&entry.path()).ok())
+            .collect::<Vec<_>>();
+        runs.sort_by_key(|run| run.metadata.started_at);
+        runs
+    }
+
+    /// Name of the directory of the run.
+    pub(crate) fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// The files of the records saved in this run.
+    pub(crate) fn record_files(&self) -> Vec<PathBuf> {
+        json_files(&self.path)
+            .into_iter()
+            .filter(|path| !path.ends_with(RUN_FILE))
+            .collect()
+    }
+
+    /// The other files of the run, like logs.
+    pub(crate) fn other_files(&self) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(&self.path) else {
+            return vec![];
+        };
+
+        let mut files = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().is_none_or(|ext| ext != "json"))
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    /// A new path to save the record of the given benchmark in this run.
+    ///
+    /// The name comes from the benches or from imported records, the characters other than ASCII
+    /// letters, digits, `-` and `_` are replaced to stay in the directory of the run.
+    pub(crate) fn record_path(&self, name: &str, timestamp: u128) -> PathBuf {
+        let name = name
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+        unique_path(&self.path, &format!("bench_{name}_{timestamp}"), ".json")
+    }
+
+    /// Environment variables saving the records of the benchmark binaries in this run.
+    pub(crate) fn envs(&self) -> Vec<(String, String)> {
+        vec![
+            (
+                DATA_DIR_ENV_VAR.to_string(),
+                data_dir().to_string_lossy().to_string(),
+            ),
+            (
+                RUN_DIR_ENV_VAR.to_string(),
+                self.path.to_string_lossy().to_string(),
+            ),
+        ]
+    }
+
+    /// Returns true if the run has every given tag.
+    pub(crate) fn has_tags(&self, tags: &[(String, String)]) -> bool {
+        tags.iter()
+            .all(|(key, value)| self.metadata.tags.get(key) == Some(value))
+    }
+}
+
+fn runs_dir() -> PathBuf {
+    data_dir().join(RUNS_DIR)
+}
+
+/// Directory of the uploads waiting to be sent again with `burnbench upload --pending`.
+pub(crate) fn uploads_dir() -> PathBuf {
+    data_dir().join(UPLOADS_DIR)
+}
+
+/// Returns true if the name is a single directory name, without separators.
+fn is_dir_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['/', '\\'])
+}
+
+/// A path in the given directory which does not exist yet, made of the given stem followed by a
+/// number if needed.
+fn unique_path(dir: &Path, stem: &str, extension: &str) -> PathBuf {
+    let mut path = dir.join(format!("{stem}{extension}"));
+    let mut suffix = 1;
+    while path.exists() {
+        path = dir.join(format!("{stem}-{suffix}{extension}"));
+        suffix += 1;
+    }
+    path
+}
+
+/// The files of all the records of the data directory, including the records saved before the
+/// runs had their own directory.
+pub(crate) fn record_files() -> Vec<PathBuf> {
+    let mut files = json_files(&data_dir());
+    files.extend(Run::list().iter().flat_map(Run::record_files));
+    files
+}
+
+fn json_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut files = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+/// Parse a tag in the `key=value` format.
+pub fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    match tag.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid tag '{tag}', expected key=value")),
+    }
+}
+
+/// Which runs are removed by the `clean` command.
+#[derive(Debug, Default)]
+pub(crate) struct RetentionPolicy {
+    /// Keep the given number of most recent runs.
+    pub(crate) keep_last: Option<usize>,
+    /// Only remove the runs older than this.
+    pub(crate) older_than: Option<Duration>,
+    /// Only remove the runs with all these tags.
+    pub(crate) tags: Vec<(String, String)>,
+}
+
+impl RetentionPolicy {
+    /// Select the runs to remove among the given runs, sorted from the oldest to the most recent.
+    ///
+    /// A run is removed when it matches every criteria of the policy.
+    pub(crate) fn select<'a>(&self, runs: &'a [Run], now: SystemTime) -> Vec<&'a Run> {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let runs = runs
+            .iter()
+            .filter(|run| run.has_tags(&self.tags))
+            .collect::<Vec<_>>();
+        let kept = self.keep_last.unwrap_or(0).min(runs.len());
+
+        runs[..runs.len() - kept]
+            .iter()
+            .filter(|run| match self.older_than {
+                Some(age) => run.metadata.started_at + age.as_millis() < now,
+                None => true,
+            })
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u128 = 24 * 3600 * 1000;
+
+    fn run(started_at: u128, tags: &[(&str, &str)]) -> Run {
+        Run {
+            path: PathBuf::from(started_at.to_string()),
+            metadata: RunMetadata {
+                started_at,
+                tags: tags
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn data_dir_follows_xdg_cache_home() {
+        assert_eq!(
+            default_data_dir(Some("/xdg".into()), Some("/home/me".into())),
+            PathBuf::from("/xdg/burn/burnbench")
+        );
+        assert_eq!(
+            default_data_dir(None, Some("/home/me".into())),
+            PathBuf::from("/home/me/.cache/burn/burnbench")
+        );
+    }
+
+    #[test]
+    fn retention_policy_selects_runs() {
+        let runs = [
+            run(DAY, &[("ci", "true")]),
+            run(2 * DAY, &[]),
+            run(9 * DAY, &[("ci", "true")]),
+            run(10 * DAY, &[]),
+        ];
+        let now = UNIX_EPOCH + Duration::from_millis(10 * DAY as u64);
+        let selected = |policy: RetentionPolicy| {
+            policy
+                .select(&runs, now)
+                .iter()
+                .map(|run| run.metadata.started_at / DAY)
+                .collect::<Vec<_>>()
+        };
+
+        let keep_last = |keep_last| RetentionPolicy {
+            keep_last: Some(keep_last),
+            ..Default::default()
+        };
+        assert_eq!(selected(keep_last(2)), vec![1, 2]);
+        assert_eq!(selected(keep_last(10)), Vec::<u128>::new());
+        assert_eq!(
+            selected(RetentionPolicy {
+                older_than: Some(Duration::from_secs(3 * 24 * 3600)),
+                ..Default::default()
+            }),
+            vec![1, 2]
+        );
+        assert_eq!(
+            selected(RetentionPolicy {
+                keep_last: Some(1),
+                tags: vec![("ci".to_string(), "true".to_string())],
+                ..Default::default()
+            }),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn run_names_stay_in_the_runs_directory() {
+        assert!(is_dir_name("20240312-014749-697"));
+        assert!(!is_dir_name(""));
+        assert!(!is_dir_name(".."));
+        assert!(!is_dir_name("../escaped"));
+        assert!(!is_dir_name("/tmp/escaped"));
+        assert!(!is_dir_name("nested/run"));
+        assert!(!is_dir_name("..\\escaped"));
+    }
+
+    #[test]
+    fn record_names_stay_in_the_run_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = Run {
+            path: dir.path().to_path_buf(),
+            metadata: RunMetadata::default(),
+        };
+
+        let path = run.record_path("../../escaped/unary 1x2", 42);
+        assert_eq!(path.parent(), Some(dir.path()));
+        assert_eq!(
+            path.file_name().unwrap(),
+            "bench_______escaped_unary_1x2_42.json"
+        );
+    }
+
+    #[test]
+    fn parse_tags() {
+        assert_eq!(
+            parse_tag("gpu=a100"),
+            Ok(("gpu".to_string(), "a100".to_string()))
+        );
+        assert_eq!(parse_tag("note="), Ok(("note".to_string(), String::new())));
+        assert!(parse_tag("gpu").is_err());
+        assert!(parse_tag("=a100").is_err());
+    }
+}
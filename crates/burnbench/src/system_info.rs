@@ -1,19 +1,63 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::fs;
 use wgpu::{self, Backends};
 
+/// Description of the machine on which the benchmarks are executed.
+///
+/// The fields missing from the records written by older versions are left to their default
+/// value.
 #[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BenchmarkSystemInfo {
     cpus: Vec<String>,
     gpus: Vec<String>,
     pub os: BenchmarkOSInfo,
+    pub memory: BenchmarkMemoryInfo,
+    pub cpu: BenchmarkCpuInfo,
+    pub toolchain: BenchmarkToolchainInfo,
 }
 
 #[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BenchmarkOSInfo {
     pub name: String,
     #[serde(rename = "wsl")]
     windows_linux_subsystem: bool,
+    #[serde(rename = "kernelVersion")]
+    pub kernel_version: Option<String>,
+}
+
+/// Memory of the machine, in bytes.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BenchmarkMemoryInfo {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Topology and frequencies of the CPUs, the values that cannot be read on the platform are
+/// `None`.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BenchmarkCpuInfo {
+    pub physical_cores: Option<usize>,
+    pub logical_cores: usize,
+    pub numa_nodes: Option<usize>,
+    pub base_frequency_mhz: Option<u64>,
+    pub max_frequency_mhz: Option<u64>,
+    pub governor: Option<String>,
+    pub smt: Option<bool>,
+}
+
+/// Toolchain used to compile the benchmarks.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BenchmarkToolchainInfo {
+    pub rustc_version: String,
+    pub rustc_channel: String,
+    pub target: String,
+    pub rustflags: Vec<String>,
 }
 
 impl From<os_info::Info> for BenchmarkOSInfo {
@@ -21,16 +65,71 @@ impl From<os_info::Info> for BenchmarkOSInfo {
         BenchmarkOSInfo {
             name: format!("{}", info),
             windows_linux_subsystem: wsl::is_wsl(),
+            kernel_version: sysinfo::System::kernel_version(),
+        }
+    }
+}
+
+impl BenchmarkMemoryInfo {
+    fn new(system: &sysinfo::System) -> Self {
+        Self {
+            total_bytes: system.total_memory(),
+            available_bytes: system.available_memory(),
+        }
+    }
+}
+
+impl BenchmarkCpuInfo {
+    fn new(system: &sysinfo::System) -> Self {
+        let physical_cores = system.physical_core_count();
+        let logical_cores = system.cpus().len();
+        let smt = read_sys_file("/sys/devices/system/cpu/smt/active")
+            .map(|active| active == "1")
+            .or_else(|| physical_cores.map(|physical| physical < logical_cores));
+
+        Self {
+            physical_cores,
+            logical_cores,
+            numa_nodes: count_numa_nodes(),
+            base_frequency_mhz: read_cpu_frequency("base_frequency"),
+            max_frequency_mhz: read_cpu_frequency("cpuinfo_max_freq"),
+            governor: read_sys_file("/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor"),
+            smt,
+        }
+    }
+}
+
+impl BenchmarkToolchainInfo {
+    fn new() -> Self {
+        let rustc_version = env!("BURNBENCH_RUSTC_VERSION").to_string();
+        Self {
+            rustc_channel: rustc_channel(&rustc_version).to_string(),
+            rustc_version,
+            target: env!("BURNBENCH_TARGET").to_string(),
+            rustflags: env!("BURNBENCH_RUSTFLAGS")
+                .split('\x1f')
+                .filter(|flag| !flag.is_empty())
+                .map(String::from)
+                .collect(),
         }
     }
 }
 
 impl BenchmarkSystemInfo {
     pub fn new() -> Self {
+        let system = sysinfo::System::new_with_specifics(
+            sysinfo::RefreshKind::nothing()
+                .with_cpu(sysinfo::CpuRefreshKind::everything())
+                .with_memory(sysinfo::MemoryRefreshKind::everything()),
+        );
+
         Self {
             cpus: BenchmarkSystemInfo::enumerate_cpus(),
             gpus: BenchmarkSystemInfo::enumerate_gpus(),
             os: BenchmarkOSInfo::from(os_info::get()),
+            memory: BenchmarkMemoryInfo::new(&system),
+            cpu: BenchmarkCpuInfo::new(&system),
+            toolchain: BenchmarkToolchainInfo::new(),
         }
     }
 
@@ -47,35 +146,116 @@ impl BenchmarkSystemInfo {
     }
 
     fn enumerate_gpus() -> Vec<String> {
-        let instance = wgpu::Instance::default();
-        let adapters: Vec<wgpu::Adapter> = instance
-            .enumerate_adapters({
-                let backend;
-                cfg_if::cfg_if! {
-                    if #[cfg(target_family = "wasm")] {
-                        backend = wgpu::Backend::BrowserWebGpu;
-                    } else if #[cfg(target_os = "macos")] {
-                         backend = wgpu::Backend::Metal;
-                    } else {
-                        backend = wgpu::Backend::Vulkan;
-                    }
-                };
-                Backends::from_bits(1 << backend as u32).unwrap()
-            })
+        let gpu_names: HashSet<String> = enumerate_gpu_adapters()
             .into_iter()
-            .filter(|adapter| {
-                let info = adapter.get_info();
-                info.device_type == wgpu::DeviceType::DiscreteGpu
-                    || info.device_type == wgpu::DeviceType::IntegratedGpu
-            })
-            .collect();
-        let gpu_names: HashSet<String> = adapters
-            .iter()
-            .map(|adapter| {
-                let info = adapter.get_info();
-                info.name
-            })
+            .map(|info| info.name)
             .collect();
         gpu_names.into_iter().collect()
     }
 }
+
+/// Enumerate the discrete and integrated GPUs visible with the native graphics API of the
+/// platform, in the order used by the backends to index them.
+pub(crate) fn enumerate_gpu_adapters() -> Vec<wgpu::AdapterInfo> {
+    let instance = wgpu::Instance::default();
+    instance
+        .enumerate_adapters({
+            let backend;
+            cfg_if::cfg_if! {
+                if #[cfg(target_family = "wasm")] {
+                    backend = wgpu::Backend::BrowserWebGpu;
+                } else if #[cfg(target_os = "macos")] {
+                     backend = wgpu::Backend::Metal;
+                } else {
+                    backend = wgpu::Backend::Vulkan;
+                }
+            };
+            Backends::from_bits(1 << backend as u32).unwrap()
+        })
+        .into_iter()
+        .map(|adapter| adapter.get_info())
+        .filter(|info| {
+            info.device_type == wgpu::DeviceType::DiscreteGpu
+                || info.device_type == wgpu::DeviceType::IntegratedGpu
+        })
+        .collect()
+}
+
+/// Read a value exposed by the kernel, `None` if it is not available on this platform.
+fn read_sys_file(path: &str) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_string())
+        .filter(|content| !content.is_empty())
+}
+
+/// Read a frequency of the first CPU in MHz, the kernel exposes them in kHz.
+fn read_cpu_frequency(name: &str) -> Option<u64> {
+    read_sys_file(&format!("/sys/devices/system/cpu/cpu0/cpufreq/{name}"))?
+        .parse::<u64>()
+        .ok()
+        .map(|khz| khz / 1000)
+}
+
+fn count_numa_nodes() -> Option<usize> {
+    let nodes = fs::read_dir("/sys/devices/system/node")
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.file_name().to_str().is_some_and(|name| {
+                name.strip_prefix("node")
+                    .is_some_and(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+            })
+        })
+        .count();
+    Some(nodes).filter(|nodes| *nodes > 0)
+}
+
+/// Release channel of the compiler from its version, e.g. `rustc 1.87.0-nightly (...)`.
+fn rustc_channel(version: &str) -> &'static str {
+    let release = version.split_whitespace().nth(1).unwrap_or_default();
+    if release.contains("nightly") || release.contains("dev") {
+        "nightly"
+    } else if release.contains("beta") {
+        "beta"
+    } else if release.is_empty() {
+        "unknown"
+    } else {
+        "stable"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rustc_channel_from_version() {
+        assert_eq!(
+            rustc_channel("rustc 1.85.0 (4d91de4e4 2025-02-17)"),
+            "stable"
+        );
+        assert_eq!(
+            rustc_channel("rustc 1.87.0-nightly (f8a913b13 2025-02-23)"),
+            "nightly"
+        );
+        assert_eq!(
+            rustc_channel("rustc 1.86.0-beta.1 (a1b2c3 2025-02-20)"),
+            "beta"
+        );
+        assert_eq!(rustc_channel("unknown"), "unknown");
+    }
+
+    #[test]
+    fn deserialize_system_info_without_details() {
+        let info: BenchmarkSystemInfo = serde_json::from_str(
+            r#"{"cpus":["AMD Ryzen"],"gpus":[],"os":{"name":"Ubuntu","wsl":false}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(info.os.name, "Ubuntu");
+        assert_eq!(info.os.kernel_version, None);
+        assert_eq!(info.memory.total_bytes, 0);
+        assert_eq!(info.toolchain.rustc_version, "");
+    }
+}